@@ -11,7 +11,8 @@ import sqlite3
 import json
 import os
 import shutil
-from datetime import datetime
+from datetime import datetime, timezone
+from zoneinfo import ZoneInfo
 import logging
 import re
 from collections import Counter
@@ -45,6 +46,7 @@ llm = None
 vectorstore = None
 embeddings = None
 qa_chain = None
+qa_prompt = None
 entry_metadata_map: Dict[str, Dict[str, Any]] = {}
 user_profile: Dict[str, Any] = {"name": None, "name_sources": []}
 PERSIST_DIR = os.path.abspath(os.path.join(os.path.dirname(__file__), "chroma_db"))
@@ -61,10 +63,20 @@ class JournalEntry(BaseModel):
     mood: Optional[str] = None
     tags: Optional[List[str]] = None
 
+class ChatScope(BaseModel):
+    # Entries carrying any of these tags, compared case-insensitively
+    tags: List[str] = []
+    # Inclusive bounds (YYYY-MM-DD) on the day each entry is about
+    date_from: Optional[str] = None
+    date_to: Optional[str] = None
+    # Zone that decides which day an entry without an explicit date falls on
+    timezone: Optional[str] = None
+
 class ChatRequest(BaseModel):
     user_id: str
     message: str
     conversation_id: Optional[str] = None
+    scope: Optional[ChatScope] = None
 
 class ChatResponse(BaseModel):
     answer: str
@@ -141,10 +153,12 @@ def load_journal_entries_to_vectorstore():
         conn = get_db_connection()
         cursor = conn.cursor()
         
-        # Get all journal entries
+        # Get all journal entries, skipping trashed ones and ones the user
+        # kept out of AI features
         cursor.execute("""
-            SELECT id, user_id, title, body, created_at, updated_at, mood, tags
+            SELECT id, user_id, title, body, created_at, updated_at, mood, tags, entry_date
             FROM entries 
+            WHERE visibility = 'normal' AND deleted_at IS NULL
             ORDER BY created_at DESC
         """)
         
@@ -162,7 +176,7 @@ def load_journal_entries_to_vectorstore():
         entry_metadata_map = {}
 
         for row in rows:
-            entry_id, user_id, title, body, created_at, updated_at, mood, tags = row
+            entry_id, user_id, title, body, created_at, updated_at, mood, tags, entry_date = row
             tags_list = json.loads(tags) if tags else []
 
             entries.append(
@@ -196,6 +210,8 @@ def load_journal_entries_to_vectorstore():
                 metadata["mood"] = mood
             if tags_list:
                 metadata["tags"] = ", ".join(tags_list)
+            if entry_date:
+                metadata["entry_date"] = entry_date
 
             doc = Document(page_content=doc_text, metadata=metadata)
             documents.append(doc)
@@ -211,7 +227,7 @@ def load_journal_entries_to_vectorstore():
         split_docs = text_splitter.split_documents(documents)
         
         # Recreate vector store from scratch
-        global vectorstore, qa_chain
+        global vectorstore, qa_chain, qa_prompt
         if os.path.exists(PERSIST_DIR):
             shutil.rmtree(PERSIST_DIR)
 
@@ -237,18 +253,12 @@ Question: {question}
 
 Answer:"""
 
-        PROMPT = PromptTemplate(
+        qa_prompt = PromptTemplate(
             template=prompt_template,
             input_variables=["context", "question"]
         )
         
-        qa_chain = RetrievalQA.from_chain_type(
-            llm=llm,
-            chain_type="stuff",
-            retriever=vectorstore.as_retriever(search_kwargs={"k": 5}),
-            chain_type_kwargs={"prompt": PROMPT},
-            return_source_documents=True
-        )
+        qa_chain = build_qa_chain()
         
         logger.info(f"✅ Loaded {len(entries)} journal entries into vector store")
         
@@ -257,6 +267,48 @@ Answer:"""
         vectorstore = None
         qa_chain = None
 
+def build_qa_chain(entry_ids: Optional[List[str]] = None):
+    """Build a QA chain, optionally retrieving only from the given entries"""
+    search_kwargs: Dict[str, Any] = {"k": 5}
+    if entry_ids is not None:
+        search_kwargs["filter"] = {"id": {"$in": entry_ids}}
+    return RetrievalQA.from_chain_type(
+        llm=llm,
+        chain_type="stuff",
+        retriever=vectorstore.as_retriever(search_kwargs=search_kwargs),
+        chain_type_kwargs={"prompt": qa_prompt},
+        return_source_documents=True
+    )
+
+def entry_day(metadata: Dict[str, Any], zone: ZoneInfo) -> str:
+    """The day (YYYY-MM-DD) an entry is about, as the Tauri app sees it"""
+    if metadata.get("entry_date"):
+        return metadata["entry_date"]
+    created_at = datetime.fromisoformat(metadata["created_at"].replace("Z", "+00:00"))
+    if created_at.tzinfo is None:
+        created_at = created_at.replace(tzinfo=timezone.utc)
+    return created_at.astimezone(zone).date().isoformat()
+
+def entries_in_scope(user_id: str, scope: ChatScope) -> List[str]:
+    """Ids of the user's indexed entries that fall within a conversation scope"""
+    zone = ZoneInfo(scope.timezone or "UTC")
+    wanted_tags = {tag.lower() for tag in scope.tags}
+    ids = []
+    for entry_id, metadata in entry_metadata_map.items():
+        if metadata.get("user_id") != user_id:
+            continue
+        if wanted_tags:
+            tags = {tag.strip().lower() for tag in metadata.get("tags", "").split(",")}
+            if not wanted_tags & tags:
+                continue
+        day = entry_day(metadata, zone)
+        if scope.date_from and day < scope.date_from:
+            continue
+        if scope.date_to and day > scope.date_to:
+            continue
+        ids.append(entry_id)
+    return ids
+
 @app.on_event("startup")
 async def startup_event():
     """Initialize RAG components on startup"""
@@ -344,14 +396,27 @@ async def chat_with_ai(request: ChatRequest):
                     conversation_id=request.conversation_id or "default",
                 )
 
+        # Retrieve only from entries inside the conversation's scope
+        chain = qa_chain
+        if request.scope:
+            scoped_ids = entries_in_scope(request.user_id, request.scope)
+            if not scoped_ids:
+                return ChatResponse(
+                    answer="None of your journal entries fall within this conversation's scope yet.",
+                    sources=[],
+                    conversation_id=request.conversation_id or "default",
+                )
+            chain = build_qa_chain(scoped_ids)
+
         # Get response from QA chain
-        result = qa_chain.invoke({"query": request.message})
+        result = chain.invoke({"query": request.message})
         
         # Extract sources
         sources = []
         if "source_documents" in result:
             for doc in result["source_documents"]:
                 source_info = {
+                    "id": doc.metadata.get("id"),
                     "title": doc.metadata.get("title", "Unknown"),
                     "date": doc.metadata.get("created_at", "Unknown"),
                     "mood": doc.metadata.get("mood", None),
@@ -0,0 +1,276 @@
+// Headless command-line access to the journal database.
+//
+// Operates on the same journal.db the desktop app uses (or an explicit
+// `--db` path), so entries can be scripted, searched, and backed up from cron.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use journal_app_lib::{
+    CreateEntryRequest, JournalEntry, PythonChatRequest, SearchFields, SearchRequest, Service,
+    ServiceConfig,
+};
+
+// Must match `identifier` in tauri.conf.json so the CLI finds the app's database
+const APP_IDENTIFIER: &str = "com.tauri.dev";
+
+#[derive(Parser)]
+#[command(name = "journal-cli", about = "Script your journal without the GUI")]
+struct Cli {
+    /// Path to the journal database (defaults to $DATABASE_PATH, then the app data directory)
+    #[arg(long, global = true)]
+    db: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a new entry
+    Add {
+        #[arg(long)]
+        title: String,
+        /// Entry body; read from stdin when omitted
+        #[arg(long)]
+        body: Option<String>,
+        #[arg(long)]
+        mood: Option<String>,
+        /// How strongly the mood was felt, 1-5
+        #[arg(long)]
+        mood_intensity: Option<u8>,
+        /// Day the entry is about (YYYY-MM-DD), if not today
+        #[arg(long)]
+        entry_date: Option<String>,
+        /// Tag to attach (repeatable)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// List entries, newest first
+    List {
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Only entries with at least this many words
+        #[arg(long)]
+        min_words: Option<i64>,
+        /// Only entries with at most this many words
+        #[arg(long)]
+        max_words: Option<i64>,
+    },
+    /// Full-text search over entries
+    Search {
+        query: String,
+        #[arg(long)]
+        limit: Option<i32>,
+        /// Only match titles or only bodies
+        #[arg(long = "in", value_enum, default_value_t = SearchIn::Both)]
+        search_in: SearchIn,
+    },
+    /// Export every entry
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Also export ai_excluded and private entries
+        #[arg(long)]
+        include_hidden: bool,
+    },
+    /// Ask the AI assistant a question (requires the Python RAG service)
+    Chat {
+        message: String,
+        /// Continue this conversation; a new one is started when omitted
+        #[arg(long)]
+        conversation_id: Option<String>,
+        /// Don't save the question or answer to chat history
+        #[arg(long)]
+        incognito: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Json,
+    Markdown,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SearchIn {
+    Title,
+    Body,
+    Both,
+}
+
+impl From<SearchIn> for SearchFields {
+    fn from(value: SearchIn) -> Self {
+        match value {
+            SearchIn::Title => SearchFields::Title,
+            SearchIn::Body => SearchFields::Body,
+            SearchIn::Both => SearchFields::Both,
+        }
+    }
+}
+
+// Same override the desktop app honors
+fn default_db_path() -> Result<PathBuf> {
+    if let Some(path) = std::env::var_os("DATABASE_PATH").filter(|p| !p.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+    let data_dir =
+        dirs::data_dir().ok_or_else(|| anyhow!("Could not resolve the data directory"))?;
+    Ok(data_dir.join(APP_IDENTIFIER).join("journal.db"))
+}
+
+fn print_entry_line(entry: &JournalEntry) {
+    println!(
+        "{}  {}  {}",
+        entry.created_at.format("%Y-%m-%d %H:%M"),
+        entry.id,
+        entry.title
+    );
+}
+
+fn render_markdown(entries: &[JournalEntry]) -> String {
+    let mut out = String::from("# Journal\n");
+    for entry in entries {
+        out.push_str(&format!("\n## {}\n\n", entry.title));
+        out.push_str(&format!(
+            "*{}*",
+            entry.created_at.format("%Y-%m-%d %H:%M UTC")
+        ));
+        if let Some(date) = entry.entry_date {
+            out.push_str(&format!(" · about {}", date));
+        }
+        if let Some(mood) = &entry.mood {
+            out.push_str(&format!(" · mood: {}", mood));
+            if let Some(intensity) = entry.mood_intensity {
+                out.push_str(&format!(" ({}/5)", intensity));
+            }
+        }
+        if let Some(tags) = entry.tags.as_ref().filter(|t| !t.is_empty()) {
+            out.push_str(&format!(" · tags: {}", tags.join(", ")));
+        }
+        for (key, value) in entry.fields.iter().flatten() {
+            match value.as_str() {
+                Some(text) => out.push_str(&format!(" · {}: {}", key, text)),
+                None => out.push_str(&format!(" · {}: {}", key, value)),
+            }
+        }
+        out.push_str("\n\n");
+        out.push_str(entry.body.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let db_path = match cli.db {
+        Some(path) => path,
+        None => default_db_path()?,
+    };
+    if let Some(parent) = db_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let db_url = format!("sqlite:{}", db_path.to_string_lossy());
+    let service = Service::connect(&db_url, ServiceConfig::default()).await?;
+
+    match cli.command {
+        Command::Add {
+            title,
+            body,
+            mood,
+            mood_intensity,
+            entry_date,
+            tags,
+        } => {
+            let body = match body {
+                Some(body) => body,
+                None => std::io::read_to_string(std::io::stdin())?,
+            };
+            let entry = service
+                .create_entry(CreateEntryRequest {
+                    title,
+                    body,
+                    mood,
+                    mood_intensity,
+                    entry_date,
+                    tags: (!tags.is_empty()).then_some(tags),
+                    ..Default::default()
+                })
+                .await?;
+            println!("{}", entry.id);
+        }
+        Command::List {
+            limit,
+            min_words,
+            max_words,
+        } => {
+            let entries = if min_words.is_some() || max_words.is_some() {
+                service
+                    .get_entries_by_word_count(min_words, max_words)
+                    .await?
+            } else {
+                service.get_entries().await?
+            };
+            for entry in entries.iter().take(limit.unwrap_or(usize::MAX)) {
+                print_entry_line(entry);
+            }
+        }
+        Command::Search {
+            query,
+            limit,
+            search_in,
+        } => {
+            let entries = service
+                .search_entries(SearchRequest {
+                    query,
+                    limit,
+                    fields: Some(search_in.into()),
+                    ..Default::default()
+                })
+                .await?;
+            for entry in &entries {
+                print_entry_line(entry);
+            }
+        }
+        Command::Export {
+            format,
+            output,
+            include_hidden,
+        } => {
+            let entries = service.get_entries_with_fields(include_hidden).await?;
+            let rendered = match format {
+                ExportFormat::Json => serde_json::to_string_pretty(&entries)?,
+                ExportFormat::Markdown => render_markdown(&entries),
+            };
+            match output {
+                Some(path) => std::fs::write(path, rendered)?,
+                None => println!("{}", rendered),
+            }
+        }
+        Command::Chat {
+            message,
+            conversation_id,
+            incognito,
+        } => {
+            let response = service
+                .chat(PythonChatRequest {
+                    user_id: service.user_id().to_string(),
+                    message,
+                    conversation_id,
+                    persist: Some(!incognito),
+                    ..Default::default()
+                })
+                .await?;
+            println!("{}", response.answer);
+            eprintln!("conversation: {}", response.conversation_id);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+// How dates are shown to the user. Entries are stored in UTC; showing them
+// in UTC puts late-evening entries on the wrong day for users far from it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DateDisplay {
+    // IANA zone name such as "America/Los_Angeles"; `None` means UTC
+    pub timezone: Option<String>,
+    // chrono strftime format
+    pub date_format: String,
+}
+
+impl Default for DateDisplay {
+    fn default() -> Self {
+        DateDisplay {
+            timezone: None,
+            date_format: "%Y-%m-%d".to_string(),
+        }
+    }
+}
+
+impl DateDisplay {
+    pub fn validate(&self) -> Result<()> {
+        self.tz()?;
+        if StrftimeItems::new(&self.date_format).any(|item| matches!(item, Item::Error)) {
+            return Err(anyhow!("Invalid date format '{}'", self.date_format));
+        }
+        Ok(())
+    }
+
+    pub fn tz(&self) -> Result<Tz> {
+        match self.timezone.as_deref() {
+            None => Ok(Tz::UTC),
+            Some(name) => Tz::from_str(name).map_err(|_| anyhow!("Unknown timezone '{}'", name)),
+        }
+    }
+
+    pub fn format(&self, date: DateTime<Utc>) -> String {
+        let tz = self.tz().unwrap_or(Tz::UTC);
+        date.with_timezone(&tz)
+            .format(&self.date_format)
+            .to_string()
+    }
+
+    // Reformats an RFC 3339 timestamp, or returns `None` if it doesn't parse
+    pub fn format_rfc3339(&self, value: &str) -> Option<String> {
+        DateTime::parse_from_rfc3339(value)
+            .ok()
+            .map(|date| self.format(date.with_timezone(&Utc)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn near_midnight_utc_shows_the_local_date() {
+        let display = DateDisplay {
+            timezone: Some("America/Los_Angeles".to_string()),
+            ..Default::default()
+        };
+        // 03:30 UTC on the 2nd is still the evening of the 1st in California
+        assert_eq!(
+            display
+                .format_rfc3339("2026-03-02T03:30:00+00:00")
+                .as_deref(),
+            Some("2026-03-01")
+        );
+        assert_eq!(
+            DateDisplay::default()
+                .format_rfc3339("2026-03-02T03:30:00+00:00")
+                .as_deref(),
+            Some("2026-03-02")
+        );
+    }
+}
@@ -1,9 +1,16 @@
-use anyhow::Result;
-use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
-use sqlx::{migrate::MigrateDatabase, sqlite::SqliteRow, Row, Sqlite, SqlitePool};
+use sqlx::sqlite::{SqliteAutoVacuum, SqliteConnectOptions, SqlitePoolOptions, SqliteRow};
+use sqlx::{migrate::MigrateDatabase, Executor, Row, Sqlite, SqlitePool};
 use uuid::Uuid;
 
+use crate::insights::sort_newest_first;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JournalEntry {
     pub id: String,
@@ -15,49 +22,722 @@ pub struct JournalEntry {
     pub created_at: DateTime<Utc>,
     #[serde(rename = "updatedAt")]
     pub updated_at: DateTime<Utc>,
+    // The day the entry is about, when that isn't the day it was written.
+    // `None` means the created_at date in the user's time zone.
+    #[serde(rename = "entryDate")]
+    pub entry_date: Option<NaiveDate>,
+    pub mood: Option<String>,
+    // How strongly the mood was felt, 1-5; `None` is neutral
+    #[serde(rename = "moodIntensity")]
+    pub mood_intensity: Option<u8>,
+    pub tags: Option<Vec<String>>,
+    pub visibility: Visibility,
+    // Sort key among pinned entries (lowest first); `None` when not pinned
+    #[serde(rename = "pinOrder")]
+    pub pin_order: Option<i64>,
+    // When the entry was moved to the trash; `None` for live entries
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    // Derived from `body` on read, not stored with the entry
+    #[serde(rename = "wordCount", default)]
+    pub word_count: i64,
+    #[serde(rename = "readingMinutes", default)]
+    pub reading_minutes: f32,
+    // Custom fields, only loaded where noted (get_entry, exports)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Option<BTreeMap<String, serde_json::Value>>,
+}
+
+// Who may see an entry. Variants are ordered from least to most restrictive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    #[default]
+    Normal,
+    // Kept out of AI features: never indexed or cited by the RAG service
+    AiExcluded,
+    // Also kept out of AI features; meant to be shown only after unlocking
+    Private,
+}
+
+impl Visibility {
+    fn as_str(self) -> &'static str {
+        match self {
+            Visibility::Normal => "normal",
+            Visibility::AiExcluded => "ai_excluded",
+            Visibility::Private => "private",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "normal" => Ok(Visibility::Normal),
+            "ai_excluded" => Ok(Visibility::AiExcluded),
+            "private" => Ok(Visibility::Private),
+            other => Err(anyhow!("Unknown visibility '{}'", other)),
+        }
+    }
+}
+
+// Lightweight view of an entry for list UIs, without the full body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntrySummary {
+    pub id: String,
+    pub title: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "entryDate")]
+    pub entry_date: Option<NaiveDate>,
     pub mood: Option<String>,
+    #[serde(rename = "moodIntensity")]
+    pub mood_intensity: Option<u8>,
     pub tags: Option<Vec<String>>,
+    pub snippet: String,
+    #[serde(rename = "wordCount")]
+    pub word_count: i64,
 }
 
+// An entry with how often the assistant has cited it as a source
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferencedEntry {
+    #[serde(flatten)]
+    pub entry: JournalEntry,
+    #[serde(rename = "retrievalCount")]
+    pub retrieval_count: i64,
+    #[serde(rename = "lastRetrievedAt")]
+    pub last_retrieved_at: Option<String>,
+}
+
+// Type of a custom entry field. Numbers and booleans are stored as SQLite
+// REAL/INTEGER values so they compare numerically; dates are stored as
+// YYYY-MM-DD or RFC 3339 text, which sorts chronologically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    String,
+    Number,
+    Bool,
+    Date,
+}
+
+impl FieldType {
+    fn as_str(self) -> &'static str {
+        match self {
+            FieldType::String => "string",
+            FieldType::Number => "number",
+            FieldType::Bool => "bool",
+            FieldType::Date => "date",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "string" => Ok(FieldType::String),
+            "number" => Ok(FieldType::Number),
+            "bool" => Ok(FieldType::Bool),
+            "date" => Ok(FieldType::Date),
+            other => Err(anyhow!("Unknown field type '{}'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl FieldOp {
+    fn as_sql(self) -> &'static str {
+        match self {
+            FieldOp::Eq => "=",
+            FieldOp::Ne => "!=",
+            FieldOp::Lt => "<",
+            FieldOp::Le => "<=",
+            FieldOp::Gt => ">",
+            FieldOp::Ge => ">=",
+        }
+    }
+}
+
+// A field value checked against its type and ready to bind
+enum FieldValue {
+    Text(String),
+    Number(f64),
+    Bool(bool),
+}
+
+impl FieldValue {
+    fn new(value: &serde_json::Value, value_type: FieldType) -> Result<Self> {
+        let mismatch = || anyhow!("Value {} is not a valid {}", value, value_type.as_str());
+        match value_type {
+            FieldType::String => value
+                .as_str()
+                .map(|v| FieldValue::Text(v.to_string()))
+                .ok_or_else(mismatch),
+            FieldType::Number => value
+                .as_f64()
+                .filter(|v| v.is_finite())
+                .map(FieldValue::Number)
+                .ok_or_else(mismatch),
+            FieldType::Bool => value.as_bool().map(FieldValue::Bool).ok_or_else(mismatch),
+            FieldType::Date => {
+                let date = value.as_str().ok_or_else(mismatch)?;
+                let valid = NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok()
+                    || DateTime::parse_from_rfc3339(date).is_ok();
+                if !valid {
+                    return Err(mismatch());
+                }
+                Ok(FieldValue::Text(date.to_string()))
+            }
+        }
+    }
+
+    fn bind<'q>(
+        self,
+        query: sqlx::query::Query<'q, Sqlite, sqlx::sqlite::SqliteArguments<'q>>,
+    ) -> sqlx::query::Query<'q, Sqlite, sqlx::sqlite::SqliteArguments<'q>> {
+        match self {
+            FieldValue::Text(v) => query.bind(v),
+            FieldValue::Number(v) => query.bind(v),
+            FieldValue::Bool(v) => query.bind(v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CreateEntryRequest {
     pub title: String,
     pub body: String,
     pub mood: Option<String>,
+    #[serde(rename = "moodIntensity")]
+    pub mood_intensity: Option<u8>,
     pub tags: Option<Vec<String>>,
+    pub visibility: Option<Visibility>,
+    // Backdates the entry; defaults to now
+    #[serde(rename = "createdAt")]
+    pub created_at: Option<DateTime<Utc>>,
+    // YYYY-MM-DD of the day the entry is about; defaults to the created_at
+    // date. Unlike created_at, this doesn't change when it was written.
+    #[serde(rename = "entryDate")]
+    pub entry_date: Option<String>,
+    // Idempotency key chosen by the client. Creating again with a key the
+    // user already used returns the existing entry instead of a duplicate.
+    #[serde(rename = "clientId")]
+    pub client_id: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct UpdateEntryRequest {
     pub id: String,
     pub title: Option<String>,
     pub body: Option<String>,
     pub mood: Option<String>,
+    #[serde(rename = "moodIntensity")]
+    pub mood_intensity: Option<u8>,
     pub tags: Option<Vec<String>>,
+    pub visibility: Option<Visibility>,
+    // YYYY-MM-DD; an empty string goes back to the created_at date
+    #[serde(rename = "entryDate")]
+    pub entry_date: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergeEntriesRequest {
+    pub into_id: String,
+    pub from_ids: Vec<String>,
+    pub separator: Option<String>,
+    // Defaults to true
+    pub union_tags: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SplitEntryRequest {
+    pub id: String,
+    // Character offsets into the body, in increasing order
+    pub split_offsets: Vec<usize>,
+    // Truncate the original to its first part (default) rather than
+    // replacing it with a new entry like the other parts
+    pub keep_original: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SearchRequest {
     pub query: String,
     pub limit: Option<i32>,
+    // 0.0 (default) ranks purely by relevance; 1.0 purely by recency
+    pub recency_weight: Option<f64>,
+    // Like recency_weight, but favoring entries edited lately rather than
+    // written lately. Off (0.0) by default; the two may be combined.
+    pub edit_recency_weight: Option<f64>,
+    // How much a title match counts relative to a body match
+    pub title_weight: Option<f64>,
+    // What a blank query returns: the most recent entries when true, nothing
+    // when false (default)
+    pub recent_if_empty: Option<bool>,
+    // Which parts of an entry the query is matched against
+    pub fields: Option<SearchFields>,
+    // Only entries written within these bounds (inclusive); either may be
+    // left open
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl SearchRequest {
+    // A range ending before it starts can't match anything
+    fn range_is_inverted(&self) -> bool {
+        matches!((self.from, self.to), (Some(from), Some(to)) if from > to)
+    }
+
+    // Bounds bound as stored, for `CREATED_RANGE_FILTER`
+    fn range_bounds(&self) -> (Option<String>, Option<String>) {
+        (
+            self.from.map(|from| from.to_rfc3339()),
+            self.to.map(|to| to.to_rfc3339()),
+        )
+    }
+}
+
+// Keeps search matches within SearchRequest::from/to; bind each bound twice
+const CREATED_RANGE_FILTER: &str =
+    "(? IS NULL OR e.created_at >= ?) AND (? IS NULL OR e.created_at <= ?)";
+
+// A page of search results with the number of matches overall, for
+// "showing 50 of 230"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchPage {
+    pub results: Vec<JournalEntry>,
+    pub total_matches: i64,
+    // More entries matched than were returned
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchFields {
+    Title,
+    Body,
+    #[default]
+    Both,
+}
+
+impl SearchFields {
+    // Restricts an FTS5 query to the matching columns
+    fn fts_query(self, query: &str) -> String {
+        match self {
+            SearchFields::Title => format!("title : ({})", query),
+            SearchFields::Body => format!("body : ({})", query),
+            SearchFields::Both => query.to_string(),
+        }
+    }
+
+    fn columns(self) -> &'static [&'static str] {
+        match self {
+            SearchFields::Title => &["e.title"],
+            SearchFields::Body => &["e.body"],
+            SearchFields::Both => &["e.title", "e.body"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResetCounts {
+    pub entries: u64,
+    pub entry_versions: u64,
+    pub entry_fields: u64,
+    pub chunks: u64,
+    pub chat_messages: u64,
+    pub user_deleted: bool,
+    // The user to use from now on; a new id when the old user was deleted
+    pub user_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopicCheck {
+    pub found: bool,
+    // Entries mentioning the topic with at least the required relevance
+    pub count: usize,
+    pub top_source: Option<EntrySummary>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateAnomalyKind {
+    Future,
+    BeforeFloor,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DateAnomaly {
+    pub entry: EntrySummary,
+    pub kind: DateAnomalyKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationSummary {
+    pub conversation_id: String,
+    pub message_count: i64,
+    pub last_message_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: i64,
+}
+
+// An earlier state of an entry, e.g. the text as typed before save-time
+// transforms rewrote it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryVersion {
+    pub id: String,
+    pub entry_id: String,
+    pub title: String,
+    pub body: String,
+    pub mood: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub saved_at: DateTime<Utc>,
+}
+
+// A piece of an entry's body, in order, for retrieval over long entries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextChunk {
+    pub id: String,
+    pub entry_id: String,
+    pub user_id: String,
+    pub text: String,
+    pub ord: i64,
+}
+
+// Chunk sizes are in characters; consecutive chunks share `overlap` of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChunkOptions {
+    pub size: usize,
+    pub overlap: usize,
+}
+
+impl Default for ChunkOptions {
+    fn default() -> Self {
+        Self {
+            size: 500,
+            overlap: 50,
+        }
+    }
+}
+
+impl ChunkOptions {
+    pub fn validate(&self) -> Result<()> {
+        if self.size == 0 {
+            return Err(anyhow!("Chunk size must be at least 1"));
+        }
+        if self.overlap >= self.size {
+            return Err(anyhow!(
+                "Chunk overlap must be smaller than the chunk size ({}), got {}",
+                self.size,
+                self.overlap
+            ));
+        }
+        Ok(())
+    }
+}
+
+// A page of entries for the list view, with the total to page through
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryPage {
+    pub entries: Vec<JournalEntry>,
+    pub total: i64,
+}
+
+// Aggregates over the metadata stored with each assistant answer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatAnalytics {
+    pub answers: i64,
+    pub avg_sources: f64,
+    pub zero_source_answers: i64,
+    // `None` until an answer with a recorded generation time exists
+    pub avg_generation_ms: Option<f64>,
+}
+
+// Two tags and the number of entries carrying both; `tag_a` sorts first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagPair {
+    pub tag_a: String,
+    pub tag_b: String,
+    pub count: i64,
+}
+
+// Outcome of `PRAGMA wal_checkpoint`; the frame counts are -1 when the
+// database isn't in WAL mode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalCheckpoint {
+    // Another connection kept the checkpoint from completing
+    pub busy: bool,
+    pub log_frames: i64,
+    pub checkpointed_frames: i64,
+}
+
+// Limits which entries a conversation's answers may cite, e.g. a "work
+// reflection" conversation kept to work-tagged entries. Unset parts don't
+// filter.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConversationScope {
+    // Entries carrying any of these tags, compared case-insensitively
+    pub tags: Vec<String>,
+    // Inclusive bounds on the day each entry is about
+    pub date_from: Option<NaiveDate>,
+    pub date_to: Option<NaiveDate>,
+}
+
+impl ConversationScope {
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.date_from.is_none() && self.date_to.is_none()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.tags.iter().any(|t| t.trim().is_empty()) {
+            return Err(anyhow!("Scope tags must not be empty"));
+        }
+        if let (Some(from), Some(to)) = (self.date_from, self.date_to) {
+            if from > to {
+                return Err(anyhow!(
+                    "Scope starts on {} but ends earlier, on {}",
+                    from,
+                    to
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // `day` is the day the entry is about, which depends on the user's zone
+    pub fn matches(&self, entry: &JournalEntry, day: NaiveDate) -> bool {
+        let tagged = self.tags.is_empty()
+            || entry.tags.iter().flatten().any(|tag| {
+                self.tags
+                    .iter()
+                    .any(|wanted| wanted.trim().eq_ignore_ascii_case(tag.trim()))
+            });
+        tagged
+            && self.date_from.map_or(true, |from| day >= from)
+            && self.date_to.map_or(true, |to| day <= to)
+    }
+}
+
+// A named set of instructions the assistant answers with, e.g. "Coach"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Persona {
+    pub id: String,
+    pub name: String,
+    pub system_prompt: String,
+    pub created_at: String,
+}
+
+// What search can do with this SQLite build
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchCapabilities {
+    // FTS5 is available: searches are ranked by relevance, and topic checks
+    // and related-entry lookups work. Without it, search matches plain text
+    // and the other two return an error.
+    pub full_text: bool,
+}
+
+// How SQLite hands space freed by deletes back to the filesystem
+// (`PRAGMA auto_vacuum`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoVacuum {
+    None,
+    Full,
+    Incremental,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseDiagnostics {
+    pub auto_vacuum: AutoVacuum,
+    pub page_size: i64,
+    pub page_count: i64,
+    // Unused pages still held by the file
+    pub freelist_count: i64,
+}
+
+// A row that belongs to no user or points at a parent that's gone
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrphanRow {
+    pub id: String,
+    pub problem: String,
 }
 
+// Checks run by `audit_ownership`, as (table, problem, query selecting the
+// offending row ids)
+const ORPHAN_CHECKS: &[(&str, &str, &str)] = &[
+    (
+        "entries",
+        "user_id does not match any user",
+        "SELECT id FROM entries WHERE user_id NOT IN (SELECT id FROM users)",
+    ),
+    (
+        "chat_messages",
+        "user_id does not match any user",
+        "SELECT id FROM chat_messages WHERE user_id NOT IN (SELECT id FROM users)",
+    ),
+    (
+        "entry_fields",
+        "entry_id does not match any entry",
+        "SELECT entry_id || '/' || key FROM entry_fields WHERE entry_id NOT IN (SELECT id FROM entries)",
+    ),
+    (
+        "personas",
+        "user_id does not match any user",
+        "SELECT id FROM personas WHERE user_id NOT IN (SELECT id FROM users)",
+    ),
+    (
+        "conversation_scopes",
+        "user_id does not match any user",
+        "SELECT conversation_id FROM conversation_scopes WHERE user_id NOT IN (SELECT id FROM users)",
+    ),
+    (
+        "entry_versions",
+        "entry_id does not match any entry",
+        "SELECT id FROM entry_versions WHERE entry_id NOT IN (SELECT id FROM entries)",
+    ),
+    (
+        "chunks",
+        "entry_id does not match any entry",
+        "SELECT id FROM chunks WHERE entry_id NOT IN (SELECT id FROM entries)",
+    ),
+    (
+        "chat_metadata",
+        "message_id does not match any chat message",
+        "SELECT message_id FROM chat_metadata WHERE message_id NOT IN (SELECT id FROM chat_messages)",
+    ),
+];
+
+// Personas every user starts with, as (name, system prompt)
+const DEFAULT_PERSONAS: &[(&str, &str)] = &[
+    (
+        "Neutral analyst",
+        "Be objective and concise. Point out patterns and facts in the entries without judging them.",
+    ),
+    (
+        "Coach",
+        "Be encouraging and practical. Focus on goals and progress, and suggest one concrete next step.",
+    ),
+    (
+        "Supportive listener",
+        "Be warm and patient. Acknowledge the feelings in the entries before offering any perspective.",
+    ),
+];
+
+// Columns read by `row_to_entry`; queries alias `entries` as `e`
+const ENTRY_COLUMNS: &str = "e.id, e.user_id, e.title, e.body, e.created_at, e.updated_at, \
+     e.entry_date, e.mood, e.mood_intensity, e.tags, e.visibility, e.pin_order, e.deleted_at";
+
+// Allowed values for an entry's mood intensity
+const MOOD_INTENSITY_RANGE: std::ops::RangeInclusive<u8> = 1..=5;
+
+// Entry dates further ahead than this are treated as clock skew rather
+// than time zone differences
+const MAX_FUTURE_SKEW_HOURS: i64 = 24;
+// Nothing in the journal can reasonably predate this
+const EARLIEST_ENTRY_YEAR: i32 = 1900;
+
+// Number of data migrations in `run_data_migrations`
+const DATA_VERSION: i64 = 2;
+
+// Length, in characters, of the body preview in entry summaries
+const SNIPPET_CHARS: usize = 160;
+
+// Reading speed behind JournalEntry::reading_minutes
+const WORDS_PER_MINUTE: f32 = 200.0;
+
+// A term in the title says more about an entry than the same term in the body
+const DEFAULT_TITLE_WEIGHT: f64 = 3.0;
+
+// Age at which an entry's recency score halves when blending search results
+const RECENCY_HALF_LIFE_DAYS: f64 = 30.0;
+
+// Upper bound on terms pulled from a passage for "similar to this text"
+const MAX_SIMILARITY_TERMS: usize = 32;
+
+const STOP_WORDS: &[&str] = &[
+    "about", "after", "again", "all", "also", "and", "any", "are", "because", "been", "before",
+    "being", "but", "can", "could", "did", "does", "doing", "for", "from", "had", "has", "have",
+    "her", "here", "him", "his", "how", "into", "its", "just", "like", "more", "most", "not",
+    "now", "off", "once", "only", "other", "our", "out", "over", "own", "same", "she", "should",
+    "some", "such", "than", "that", "the", "their", "them", "then", "there", "these", "they",
+    "this", "those", "through", "too", "under", "until", "very", "was", "were", "what", "when",
+    "where", "which", "while", "who", "why", "will", "with", "would", "you", "your",
+];
+
+const PERSONA_COLUMNS: &str = "id, name, system_prompt, created_at";
+
+const CHAT_MESSAGE_COLUMNS: &str =
+    "id, user_id, conversation_id, content, is_user, sources, is_bookmarked, created_at";
+
 #[derive(Clone)]
 pub struct Database {
     pool: SqlitePool,
+    // False on SQLite builds without FTS5; search then matches with LIKE and
+    // no `entry_fts` index is kept
+    fts_enabled: bool,
 }
 
 impl Database {
     pub async fn new(database_url: &str) -> Result<Self> {
+        Self::open(database_url, false).await
+    }
+
+    // Like `new`, optionally asking for incremental auto-vacuum. SQLite only
+    // applies that to a database without tables; an existing file keeps its
+    // mode until it is rebuilt with VACUUM.
+    pub async fn open(database_url: &str, incremental_vacuum: bool) -> Result<Self> {
+        if is_in_memory_url(database_url) {
+            return Self::with_pool(in_memory_pool(database_url).await?).await;
+        }
+
         // Create database if it doesn't exist
-        if !Sqlite::database_exists(database_url).await.unwrap_or(false) {
+        let created = !Sqlite::database_exists(database_url).await.unwrap_or(false);
+        if created {
             Sqlite::create_database(database_url).await?;
             log::info!("Created database: {}", database_url);
         }
 
-        let pool = SqlitePool::connect(database_url).await?;
+        let mut options = SqliteConnectOptions::from_str(database_url)?;
+        if incremental_vacuum {
+            options = options.auto_vacuum(SqliteAutoVacuum::Incremental);
+        }
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+        if incremental_vacuum && created {
+            // create_database already initialized the file, so the mode
+            // only takes effect after a VACUUM, which is instant while empty
+            sqlx::query("VACUUM").execute(&pool).await?;
+        }
+        Self::with_pool(pool).await
+    }
+
+    // A fresh, empty database that lives only as long as this `Database`
+    // (and its clones). Nothing is written to disk and nothing persists, which
+    // makes it suitable for tests.
+    pub async fn new_in_memory() -> Result<Self> {
+        Self::new("sqlite::memory:").await
+    }
 
-        let db = Database { pool };
+    async fn with_pool(pool: SqlitePool) -> Result<Self> {
+        let mut db = Database {
+            pool,
+            fts_enabled: false,
+        };
+        db.fts_enabled = db.fts5_available().await?;
+        if !db.fts_enabled {
+            log::warn!("SQLite was built without FTS5; search falls back to plain text matching");
+        }
 
         // Run migrations
         db.create_tables().await?;
@@ -65,6 +745,103 @@ impl Database {
         Ok(db)
     }
 
+    // Probes for the module with a throwaway table, since FTS5 can be
+    // compiled in or loaded as an extension
+    async fn fts5_available(&self) -> Result<bool> {
+        let mut conn = self.pool.acquire().await?;
+        let probe = sqlx::query("CREATE VIRTUAL TABLE temp.fts5_probe USING fts5(body)")
+            .execute(&mut *conn)
+            .await;
+        if probe.is_err() {
+            return Ok(false);
+        }
+        sqlx::query("DROP TABLE temp.fts5_probe")
+            .execute(&mut *conn)
+            .await?;
+        Ok(true)
+    }
+
+    pub fn search_capabilities(&self) -> SearchCapabilities {
+        SearchCapabilities {
+            full_text: self.fts_enabled,
+        }
+    }
+
+    fn require_fts(&self) -> Result<()> {
+        if !self.fts_enabled {
+            return Err(anyhow!(
+                "Full-text search is not available in this SQLite build"
+            ));
+        }
+        Ok(())
+    }
+
+    // Waits for in-flight queries to finish, then closes every connection
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    // Releases the free pages at the end of the file. Does nothing unless the
+    // database is in incremental auto-vacuum mode.
+    pub async fn incremental_vacuum(&self) -> Result<()> {
+        sqlx::query("PRAGMA incremental_vacuum")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // Switches the auto_vacuum mode of the open database. The mode is kept in
+    // the file itself, but only takes effect once the file is rebuilt, so
+    // this runs a full VACUUM on the same connection.
+    pub async fn set_auto_vacuum(&self, mode: AutoVacuum) -> Result<()> {
+        let pragma = match mode {
+            AutoVacuum::None => "NONE",
+            AutoVacuum::Full => "FULL",
+            AutoVacuum::Incremental => "INCREMENTAL",
+        };
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query(&format!("PRAGMA auto_vacuum = {}", pragma))
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query("VACUUM").execute(&mut *conn).await?;
+        Ok(())
+    }
+
+    pub async fn diagnostics(&self) -> Result<DatabaseDiagnostics> {
+        let auto_vacuum = match self.pragma_value("auto_vacuum").await? {
+            0 => AutoVacuum::None,
+            1 => AutoVacuum::Full,
+            2 => AutoVacuum::Incremental,
+            other => return Err(anyhow!("Unknown auto_vacuum mode {}", other)),
+        };
+        Ok(DatabaseDiagnostics {
+            auto_vacuum,
+            page_size: self.pragma_value("page_size").await?,
+            page_count: self.pragma_value("page_count").await?,
+            freelist_count: self.pragma_value("freelist_count").await?,
+        })
+    }
+
+    async fn pragma_value(&self, pragma: &str) -> Result<i64> {
+        let row = sqlx::query(&format!("PRAGMA {}", pragma))
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.try_get(0)?)
+    }
+
+    // Copies everything in the write-ahead log into the main database file
+    // and truncates the log, so the .db file alone is a complete copy
+    pub async fn checkpoint_wal(&self) -> Result<WalCheckpoint> {
+        let row = sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(WalCheckpoint {
+            busy: row.try_get::<i64, _>(0)? != 0,
+            log_frames: row.try_get(1)?,
+            checkpointed_frames: row.try_get(2)?,
+        })
+    }
+
     async fn create_tables(&self) -> Result<()> {
         // Users table
         sqlx::query(
@@ -89,8 +866,19 @@ impl Database {
                 body TEXT NOT NULL,
                 created_at TEXT NOT NULL,
                 updated_at TEXT NOT NULL,
+                entry_date TEXT,
                 mood TEXT,
+                mood_intensity INTEGER,
                 tags TEXT,
+                retrieval_count INTEGER NOT NULL DEFAULT 0,
+                last_retrieved_at TEXT,
+                visibility TEXT NOT NULL DEFAULT 'normal',
+                pin_order INTEGER,
+                word_count INTEGER,
+                client_id TEXT,
+                deleted_at TEXT,
+                created_day TEXT,
+                created_day_source TEXT,
                 FOREIGN KEY (user_id) REFERENCES users (id)
             )
             "#,
@@ -98,20 +886,54 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        self.add_column_if_missing("entries", "retrieval_count", "INTEGER NOT NULL DEFAULT 0")
+            .await?;
+        self.add_column_if_missing("entries", "last_retrieved_at", "TEXT")
+            .await?;
+        self.add_column_if_missing("entries", "visibility", "TEXT NOT NULL DEFAULT 'normal'")
+            .await?;
+        self.add_column_if_missing("entries", "pin_order", "INTEGER")
+            .await?;
+        self.add_column_if_missing("entries", "mood_intensity", "INTEGER")
+            .await?;
+        self.add_column_if_missing("entries", "client_id", "TEXT")
+            .await?;
+        self.add_column_if_missing("entries", "entry_date", "TEXT")
+            .await?;
+        self.add_column_if_missing("entries", "word_count", "INTEGER")
+            .await?;
+        self.add_column_if_missing("entries", "deleted_at", "TEXT")
+            .await?;
+        // Local day of created_at, cached for paging; see refresh_created_days
+        self.add_column_if_missing("entries", "created_day", "TEXT")
+            .await?;
+        self.add_column_if_missing("entries", "created_day_source", "TEXT")
+            .await?;
+        self.backfill_word_counts(false).await?;
+
         // FTS5 virtual tables for full-text search
-        sqlx::query(
-            r#"
-            CREATE VIRTUAL TABLE IF NOT EXISTS entry_fts USING fts5(
-                id UNINDEXED,
-                title,
-                body,
-                content='entries',
-                content_rowid='rowid'
+        if self.fts_enabled {
+            let fts_existed = self.table_exists("entry_fts").await?;
+            sqlx::query(
+                r#"
+                CREATE VIRTUAL TABLE IF NOT EXISTS entry_fts USING fts5(
+                    id UNINDEXED,
+                    title,
+                    body,
+                    content='entries',
+                    content_rowid='rowid'
+                )
+                "#,
             )
-            "#,
-        )
-        .execute(&self.pool)
-        .await?;
+            .execute(&self.pool)
+            .await?;
+
+            // A new index starts out empty, e.g. when FTS5 wasn't available
+            // before, so fill it from the entries already there
+            if !fts_existed {
+                self.rebuild_fts_index().await?;
+            }
+        }
 
         // Chat messages table
         sqlx::query(
@@ -119,8 +941,11 @@ impl Database {
             CREATE TABLE IF NOT EXISTS chat_messages (
                 id TEXT PRIMARY KEY,
                 user_id TEXT NOT NULL,
+                conversation_id TEXT NOT NULL DEFAULT 'default',
                 content TEXT NOT NULL,
                 is_user BOOLEAN NOT NULL,
+                sources TEXT,
+                is_bookmarked BOOLEAN NOT NULL DEFAULT 0,
                 created_at TEXT NOT NULL,
                 FOREIGN KEY (user_id) REFERENCES users (id)
             )
@@ -129,17 +954,288 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
-        // Create indexes
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_entries_user_id ON entries (user_id)")
-            .execute(&self.pool)
-            .await?;
-
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_entries_created_at ON entries (created_at)")
-            .execute(&self.pool)
+        // Messages stored before conversations existed belong to 'default'
+        self.add_column_if_missing(
+            "chat_messages",
+            "conversation_id",
+            "TEXT NOT NULL DEFAULT 'default'",
+        )
+        .await?;
+        self.add_column_if_missing("chat_messages", "sources", "TEXT")
             .await?;
+        self.add_column_if_missing(
+            "chat_messages",
+            "is_bookmarked",
+            "BOOLEAN NOT NULL DEFAULT 0",
+        )
+        .await?;
 
-        log::info!("Database tables created successfully");
-        Ok(())
+        // Custom per-entry fields; `value` holds TEXT, REAL, or INTEGER
+        // depending on `value_type`
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS entry_fields (
+                entry_id TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value NOT NULL,
+                value_type TEXT NOT NULL,
+                PRIMARY KEY (entry_id, key),
+                FOREIGN KEY (entry_id) REFERENCES entries (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Created lazily, so existing users get the defaults exactly once
+        let seed_personas = !self.table_exists("personas").await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS personas (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                system_prompt TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                UNIQUE (user_id, name),
+                FOREIGN KEY (user_id) REFERENCES users (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        if seed_personas {
+            let user_ids: Vec<String> = sqlx::query_scalar("SELECT id FROM users")
+                .fetch_all(&self.pool)
+                .await?;
+            for user_id in user_ids {
+                self.seed_default_personas(&user_id).await?;
+            }
+        }
+
+        // Scope is a JSON-encoded ConversationScope
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS conversation_scopes (
+                user_id TEXT NOT NULL,
+                conversation_id TEXT NOT NULL,
+                scope TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                PRIMARY KEY (user_id, conversation_id),
+                FOREIGN KEY (user_id) REFERENCES users (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // One row per stored assistant answer, for chat analytics
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS chat_metadata (
+                message_id TEXT PRIMARY KEY,
+                source_count INTEGER NOT NULL,
+                generation_ms INTEGER,
+                created_at TEXT NOT NULL,
+                FOREIGN KEY (message_id) REFERENCES chat_messages (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS entry_versions (
+                id TEXT PRIMARY KEY,
+                entry_id TEXT NOT NULL,
+                title TEXT NOT NULL,
+                body TEXT NOT NULL,
+                mood TEXT,
+                tags TEXT,
+                saved_at TEXT NOT NULL,
+                FOREIGN KEY (entry_id) REFERENCES entries (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS chunks (
+                id TEXT PRIMARY KEY,
+                entry_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                text TEXT NOT NULL,
+                ord INTEGER NOT NULL,
+                FOREIGN KEY (entry_id) REFERENCES entries (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Create indexes
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_entries_user_id ON entries (user_id)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_entries_created_at ON entries (created_at)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_entries_user_created ON entries (user_id, created_at DESC, id DESC)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_entry_fields_key ON entry_fields (key, value)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_entry_versions_entry ON entry_versions (entry_id, saved_at)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_chunks_entry ON chunks (entry_id, ord)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_chat_messages_conversation ON chat_messages (user_id, conversation_id, created_at)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_entries_user_words ON entries (user_id, word_count)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_entries_user_day ON entries (user_id, COALESCE(entry_date, created_day))",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_entries_user_client_id ON entries (user_id, client_id) WHERE client_id IS NOT NULL",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        self.run_data_migrations().await?;
+
+        log::info!("Database tables created successfully");
+        Ok(())
+    }
+
+    // One-off rewrites of existing data. The last one applied is recorded
+    // in PRAGMA user_version, so each runs once per database.
+    async fn run_data_migrations(&self) -> Result<()> {
+        let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+            .fetch_one(&self.pool)
+            .await?;
+
+        // Realigns indexes written by older builds with the content table's
+        // rowids
+        if version < 1 && self.fts_enabled {
+            self.rebuild_fts_index().await?;
+        }
+
+        // Standalone markdown markers stopped counting as words, so counts
+        // stored before then are recomputed
+        if version < 2 {
+            self.backfill_word_counts(true).await?;
+        }
+
+        if version < DATA_VERSION {
+            sqlx::query(&format!("PRAGMA user_version = {}", DATA_VERSION))
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    // Re-derives the whole search index from `entries`, repairing one the
+    // audit finds out of sync
+    pub async fn rebuild_search_index(&self) -> Result<()> {
+        self.require_fts()?;
+        self.rebuild_fts_index().await
+    }
+
+    async fn rebuild_fts_index(&self) -> Result<()> {
+        sqlx::query("INSERT INTO entry_fts (entry_fts) VALUES ('rebuild')")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // Fills in `word_count` for entries written before the column existed,
+    // or for every entry when `all` is set. Counting matches `word_count()`,
+    // which SQL can't express.
+    async fn backfill_word_counts(&self, all: bool) -> Result<()> {
+        let rows: Vec<(String, String)> =
+            sqlx::query_as("SELECT id, body FROM entries WHERE word_count IS NULL OR ?")
+                .bind(all)
+                .fetch_all(&self.pool)
+                .await?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = self.pool.begin().await?;
+        for (id, body) in &rows {
+            sqlx::query("UPDATE entries SET word_count = ? WHERE id = ?")
+                .bind(word_count(body) as i64)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        log::info!("Backfilled word counts for {} entries", rows.len());
+        Ok(())
+    }
+
+    // Adds a column to an existing table when an older database predates it
+    async fn add_column_if_missing(
+        &self,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> Result<()> {
+        let columns = sqlx::query(&format!("PRAGMA table_info({})", table))
+            .fetch_all(&self.pool)
+            .await?;
+        let exists = columns
+            .iter()
+            .any(|row| row.get::<String, _>("name") == column);
+
+        if !exists {
+            sqlx::query(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table, column, definition
+            ))
+            .execute(&self.pool)
+            .await?;
+            log::info!("Added column {}.{}", table, column);
+        }
+
+        Ok(())
+    }
+
+    // Whether a table (including a virtual one) has been created
+    async fn table_exists(&self, table: &str) -> Result<bool> {
+        let row = sqlx::query("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?")
+            .bind(table)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.is_some())
     }
 
     pub async fn create_user(&self, email: &str) -> Result<String> {
@@ -152,10 +1248,28 @@ impl Database {
             .bind(&now)
             .execute(&self.pool)
             .await?;
+        self.seed_default_personas(&id).await?;
 
         Ok(id)
     }
 
+    async fn seed_default_personas(&self, user_id: &str) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        for (name, system_prompt) in DEFAULT_PERSONAS {
+            sqlx::query(
+                "INSERT OR IGNORE INTO personas (id, user_id, name, system_prompt, created_at) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(user_id)
+            .bind(name)
+            .bind(system_prompt)
+            .bind(&now)
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(())
+    }
+
     pub async fn get_or_create_user(&self, email: &str) -> Result<String> {
         // First try to find existing user by email
         let existing_user = sqlx::query("SELECT id FROM users WHERE email = ?")
@@ -171,6 +1285,129 @@ impl Database {
         self.create_user(email).await
     }
 
+    // Deletes everything the user has written, including FTS rows, in one
+    // transaction. The user row itself is kept unless `delete_user` is set.
+    pub async fn reset_user_data(&self, user_id: &str, delete_user: bool) -> Result<ResetCounts> {
+        let mut tx = self.pool.begin().await?;
+
+        if self.fts_enabled {
+            sqlx::query(
+                "INSERT INTO entry_fts (entry_fts, rowid, id, title, body) SELECT 'delete', rowid, id, title, body FROM entries WHERE user_id = ?",
+            )
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        // Deleted explicitly rather than left to the cascade so they can be
+        // counted
+        let mut entry_rows = [0; 3];
+        for (table, count) in ["entry_versions", "entry_fields", "chunks"]
+            .iter()
+            .zip(entry_rows.iter_mut())
+        {
+            *count = sqlx::query(&format!(
+                "DELETE FROM {} WHERE entry_id IN (SELECT id FROM entries WHERE user_id = ?)",
+                table
+            ))
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+        let [entry_versions, entry_fields, chunks] = entry_rows;
+
+        let entries = sqlx::query("DELETE FROM entries WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+        let chat_messages = sqlx::query("DELETE FROM chat_messages WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+        sqlx::query("DELETE FROM conversation_scopes WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let user_deleted = if delete_user {
+            sqlx::query("DELETE FROM personas WHERE user_id = ?")
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM users WHERE id = ?")
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected()
+                > 0
+        } else {
+            false
+        };
+
+        tx.commit().await?;
+
+        Ok(ResetCounts {
+            entries,
+            entry_versions,
+            entry_fields,
+            chunks,
+            chat_messages,
+            user_deleted,
+            user_id: user_id.to_string(),
+        })
+    }
+
+    // Read-only scan for rows that escaped their owner, across all users.
+    // Findings are grouped by table; tables with none are left out.
+    pub async fn audit_ownership(&self) -> Result<BTreeMap<String, Vec<OrphanRow>>> {
+        let mut findings: BTreeMap<String, Vec<OrphanRow>> = BTreeMap::new();
+
+        for (table, problem, query) in ORPHAN_CHECKS {
+            let ids: Vec<String> = sqlx::query_scalar(query).fetch_all(&self.pool).await?;
+            if ids.is_empty() {
+                continue;
+            }
+            findings
+                .entry(table.to_string())
+                .or_default()
+                .extend(ids.into_iter().map(|id| OrphanRow {
+                    id,
+                    problem: problem.to_string(),
+                }));
+        }
+
+        // The search index is derived from `entries`; with rank 1 the
+        // integrity check compares it against the entries themselves, so a
+        // failure means it holds text for deleted entries or misses some
+        let integrity = if self.fts_enabled {
+            sqlx::query("INSERT INTO entry_fts (entry_fts, rank) VALUES ('integrity-check', 1)")
+                .execute(&self.pool)
+                .await
+                .map(|_| ())
+        } else {
+            Ok(())
+        };
+        if let Err(e) = integrity {
+            log::warn!("Search index integrity check failed: {}", e);
+            findings
+                .entry("entry_fts".to_string())
+                .or_default()
+                .push(OrphanRow {
+                    id: String::new(),
+                    problem:
+                        "Search index is out of sync with entries; run rebuild_search_index to repair it"
+                            .to_string(),
+                });
+        }
+
+        Ok(findings)
+    }
+
     pub async fn create_entry(
         &self,
         user_id: &str,
@@ -178,49 +1415,117 @@ impl Database {
     ) -> Result<JournalEntry> {
         let id = Uuid::new_v4().to_string();
         let now = Utc::now();
+        let created_at = match request.created_at {
+            Some(date) => {
+                if let Some(kind) = date_anomaly(date, now) {
+                    return Err(anyhow!("{}", date_anomaly_message(date, kind)));
+                }
+                date
+            }
+            None => now,
+        };
+        validate_mood_intensity(request.mood_intensity)?;
+        let entry_date = request
+            .entry_date
+            .as_deref()
+            .map(parse_entry_date)
+            .transpose()?;
+        if let Some(client_id) = &request.client_id {
+            if client_id.trim().is_empty() {
+                return Err(anyhow!("Client id must not be empty"));
+            }
+            if let Some(existing) = self.get_entry_by_client_id(user_id, client_id).await? {
+                return Ok(existing);
+            }
+        }
+        let visibility = request.visibility.unwrap_or_default();
         let tags_json = request
             .tags
             .as_ref()
             .map(|t| serde_json::to_string(t).unwrap());
 
-        sqlx::query(
-            "INSERT INTO entries (id, user_id, title, body, created_at, updated_at, mood, tags) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+        // A concurrent create with the same client id may have won the race
+        // since the lookup above; the unique index turns that into a no-op
+        let result = sqlx::query(
+            "INSERT INTO entries (id, user_id, title, body, created_at, updated_at, entry_date, mood, mood_intensity, tags, visibility, word_count, client_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT DO NOTHING"
         )
         .bind(&id)
         .bind(user_id)
         .bind(&request.title)
         .bind(&request.body)
+        .bind(created_at.to_rfc3339())
         .bind(now.to_rfc3339())
-        .bind(now.to_rfc3339())
+        .bind(entry_date)
         .bind(&request.mood)
+        .bind(request.mood_intensity)
         .bind(&tags_json)
+        .bind(visibility.as_str())
+        .bind(word_count(&request.body) as i64)
+        .bind(&request.client_id)
         .execute(&self.pool)
         .await?;
+        if result.rows_affected() == 0 {
+            let client_id = request.client_id.as_deref().unwrap_or_default();
+            return self
+                .get_entry_by_client_id(user_id, client_id)
+                .await?
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Client id {} belongs to an entry in the trash; restore it instead",
+                        client_id
+                    )
+                });
+        }
 
         // Insert into FTS
-        sqlx::query("INSERT INTO entry_fts (id, title, body) VALUES (?, ?, ?)")
-            .bind(&id)
-            .bind(&request.title)
-            .bind(&request.body)
-            .execute(&self.pool)
-            .await?;
+        self.index_entry_fts(&self.pool, &id).await?;
 
         Ok(JournalEntry {
             id,
             user_id: user_id.to_string(),
             title: request.title.clone(),
             body: request.body.clone(),
-            created_at: now,
+            created_at,
             updated_at: now,
+            entry_date,
             mood: request.mood.clone(),
+            mood_intensity: request.mood_intensity,
             tags: request.tags.clone(),
+            visibility,
+            pin_order: None,
+            deleted_at: None,
+            word_count: word_count(&request.body) as i64,
+            reading_minutes: reading_minutes(&request.body),
+            fields: None,
         })
     }
 
-    pub async fn get_entries(&self, user_id: &str) -> Result<Vec<JournalEntry>> {
-        let rows = sqlx::query(
-            "SELECT id, user_id, title, body, created_at, updated_at, mood, tags FROM entries WHERE user_id = ? ORDER BY created_at DESC"
-        )
+    // Trashed entries aren't returned, but their client id stays taken
+    // until the entry is purged, so a retried create of one fails rather
+    // than handing back (or silently recreating) the entry
+    pub async fn get_entry_by_client_id(
+        &self,
+        user_id: &str,
+        client_id: &str,
+    ) -> Result<Option<JournalEntry>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM entries e WHERE e.user_id = ? AND e.client_id = ? AND e.deleted_at IS NULL",
+            ENTRY_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(client_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| self.row_to_entry(row)).transpose()
+    }
+
+    // In list order (`insights::sort_newest_first`), with days taken in `tz`
+    pub async fn get_entries(&self, user_id: &str, tz: Tz) -> Result<Vec<JournalEntry>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM entries e WHERE e.user_id = ? AND e.deleted_at IS NULL",
+            ENTRY_COLUMNS
+        ))
         .bind(user_id)
         .fetch_all(&self.pool)
         .await?;
@@ -229,239 +1534,3128 @@ impl Database {
         for row in rows {
             entries.push(self.row_to_entry(row)?);
         }
-
+        sort_newest_first(&mut entries, tz);
         Ok(entries)
     }
 
-    pub async fn get_entry(&self, id: &str) -> Result<Option<JournalEntry>> {
-        let row = sqlx::query(
-            "SELECT id, user_id, title, body, created_at, updated_at, mood, tags FROM entries WHERE id = ?"
-        )
-        .bind(id)
-        .fetch_optional(&self.pool)
-        .await?;
-
-        match row {
-            Some(row) => Ok(Some(self.row_to_entry(row)?)),
-            None => Ok(None),
+    // One page of `get_entries`, in the same order, sorted and paged in SQL
+    // on the cached local days
+    pub async fn get_entries_paged(
+        &self,
+        user_id: &str,
+        tz: Tz,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<JournalEntry>> {
+        if limit < 1 {
+            return Err(anyhow!("Page size must be at least 1"));
+        }
+        if offset < 0 {
+            return Err(anyhow!("Page offset must not be negative"));
         }
-    }
 
-    pub async fn update_entry(&self, request: UpdateEntryRequest) -> Result<Option<JournalEntry>> {
-        let now = Utc::now();
+        self.refresh_created_days(user_id, tz).await?;
 
-        // Build dynamic update query
-        let mut query_parts = vec!["UPDATE entries SET updated_at = ?"];
-        let now_string = now.to_rfc3339();
-        let mut bind_values: Vec<String> = vec![now_string];
-        let mut _has_updates = false;
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {} FROM entries e
+            WHERE e.user_id = ? AND e.deleted_at IS NULL
+            ORDER BY COALESCE(e.entry_date, e.created_day) DESC, e.created_at DESC, e.id DESC
+            LIMIT ? OFFSET ?
+            "#,
+            ENTRY_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
 
-        if let Some(ref title) = request.title {
-            query_parts.push("title = ?");
-            bind_values.push(title.clone());
-            _has_updates = true;
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(self.row_to_entry(row)?);
         }
+        Ok(entries)
+    }
 
-        if let Some(ref body) = request.body {
-            query_parts.push("body = ?");
-            bind_values.push(body.clone());
-            _has_updates = true;
+    // SQL has no time zones, so the local day of each entry's created_at is
+    // cached in `created_day`. `created_day_source` records the zone and
+    // timestamp it was worked out from; rows whose source no longer matches,
+    // after an edit or a change of zone, are recomputed here.
+    async fn refresh_created_days(&self, user_id: &str, tz: Tz) -> Result<()> {
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            r#"
+            SELECT id, created_at FROM entries
+            WHERE user_id = ? AND created_day_source IS NOT (? || ' ' || created_at)
+            "#,
+        )
+        .bind(user_id)
+        .bind(tz.name())
+        .fetch_all(&self.pool)
+        .await?;
+        if rows.is_empty() {
+            return Ok(());
         }
 
-        if let Some(ref mood) = request.mood {
-            query_parts.push("mood = ?");
-            bind_values.push(mood.clone());
-            _has_updates = true;
+        let mut tx = self.pool.begin().await?;
+        for (id, created_at) in &rows {
+            let day = DateTime::parse_from_rfc3339(created_at)?
+                .with_timezone(&tz)
+                .date_naive();
+            sqlx::query("UPDATE entries SET created_day = ?, created_day_source = ? WHERE id = ?")
+                .bind(day.to_string())
+                .bind(format!("{} {}", tz.name(), created_at))
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
         }
-
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn count_entries(&self, user_id: &str) -> Result<i64> {
+        let count = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM entries WHERE user_id = ? AND deleted_at IS NULL",
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count)
+    }
+
+    // Entries whose word count falls within the inclusive bounds, in the
+    // same order as `get_entries`. Either bound may be left open.
+    pub async fn get_entries_by_word_count(
+        &self,
+        user_id: &str,
+        tz: Tz,
+        min_words: Option<i64>,
+        max_words: Option<i64>,
+    ) -> Result<Vec<JournalEntry>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {}
+            FROM entries e
+            WHERE e.user_id = ? AND e.deleted_at IS NULL
+              AND (? IS NULL OR e.word_count >= ?)
+              AND (? IS NULL OR e.word_count <= ?)
+            "#,
+            ENTRY_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(min_words)
+        .bind(min_words)
+        .bind(max_words)
+        .bind(max_words)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(self.row_to_entry(row)?);
+        }
+        sort_newest_first(&mut entries, tz);
+        Ok(entries)
+    }
+
+    // Same entries and order as `get_entries`, as summaries
+    pub async fn get_entry_summaries(&self, user_id: &str, tz: Tz) -> Result<Vec<EntrySummary>> {
+        let entries = self.get_entries(user_id, tz).await?;
+        Ok(entries.into_iter().map(EntrySummary::from).collect())
+    }
+
+    // The user's entries among `ids`, in the order requested. Ids that don't
+    // exist or belong to someone else are skipped, as are repeats.
+    pub async fn get_entries_by_ids(
+        &self,
+        user_id: &str,
+        ids: &[String],
+    ) -> Result<Vec<JournalEntry>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let placeholders = vec!["?"; ids.len()].join(", ");
+        let query_str = format!(
+            "SELECT {} FROM entries e WHERE e.user_id = ? AND e.deleted_at IS NULL AND e.id IN ({})",
+            ENTRY_COLUMNS, placeholders
+        );
+
+        let mut query = sqlx::query(&query_str).bind(user_id);
+        for id in ids {
+            query = query.bind(id);
+        }
+        let rows = query.fetch_all(&self.pool).await?;
+
+        let mut found = HashMap::new();
+        for row in rows {
+            let entry = self.row_to_entry(row)?;
+            found.insert(entry.id.clone(), entry);
+        }
+        Ok(ids.iter().filter_map(|id| found.remove(id)).collect())
+    }
+
+    pub async fn entry_exists(&self, user_id: &str, id: &str) -> Result<bool> {
+        let row = sqlx::query(
+            "SELECT 1 FROM entries WHERE user_id = ? AND id = ? AND deleted_at IS NULL LIMIT 1",
+        )
+        .bind(user_id)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.is_some())
+    }
+
+    // Ignores surrounding whitespace and ASCII case
+    pub async fn title_exists(&self, user_id: &str, title: &str) -> Result<bool> {
+        let row = sqlx::query(
+            "SELECT 1 FROM entries WHERE user_id = ? AND deleted_at IS NULL AND trim(title) = ? COLLATE NOCASE LIMIT 1",
+        )
+        .bind(user_id)
+        .bind(title.trim())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.is_some())
+    }
+
+    pub async fn get_entry(&self, id: &str) -> Result<Option<JournalEntry>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM entries e WHERE e.id = ?",
+            ENTRY_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => Ok(Some(self.row_to_entry(row)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn update_entry(&self, request: UpdateEntryRequest) -> Result<Option<JournalEntry>> {
+        validate_mood_intensity(request.mood_intensity)?;
+        let entry_date = match request.entry_date.as_deref().map(str::trim) {
+            None => None,
+            Some("") => Some(None),
+            Some(date) => Some(Some(parse_entry_date(date)?)),
+        };
+        // Entries in the trash can't be edited until they're restored
+        let existing = match self.get_entry(&request.id).await? {
+            Some(entry) if entry.deleted_at.is_none() => entry,
+            _ => return Ok(None),
+        };
+
+        // Only title/body edits count as content changes. Metadata such as
+        // mood, intensity, tags, visibility, and the entry date are updated
+        // in place without touching updated_at or FTS.
+        let content_changed = request.title.as_ref().is_some_and(|t| *t != existing.title)
+            || request.body.as_ref().is_some_and(|b| *b != existing.body);
+
+        // The fields kept in entry_versions; when any of them changes, the
+        // entry as it was is saved first so the edit can be undone
+        let versioned_changed = content_changed
+            || request
+                .mood
+                .as_ref()
+                .is_some_and(|m| Some(m) != existing.mood.as_ref())
+            || request
+                .tags
+                .as_ref()
+                .is_some_and(|t| *t != existing.tags.clone().unwrap_or_default());
+
+        // Build dynamic update query
+        let mut assignments = Vec::new();
+        let mut bind_values: Vec<String> = Vec::new();
+
+        if content_changed {
+            assignments.push("updated_at = ?");
+            bind_values.push(Utc::now().to_rfc3339());
+        }
+
+        if let Some(ref title) = request.title {
+            assignments.push("title = ?");
+            bind_values.push(title.clone());
+        }
+
+        if let Some(ref body) = request.body {
+            assignments.push("body = ?");
+            bind_values.push(body.clone());
+            assignments.push("word_count = ?");
+            bind_values.push(word_count(body).to_string());
+        }
+
+        if let Some(ref mood) = request.mood {
+            assignments.push("mood = ?");
+            bind_values.push(mood.clone());
+        }
+
+        if let Some(intensity) = request.mood_intensity {
+            assignments.push("mood_intensity = ?");
+            bind_values.push(intensity.to_string());
+        }
+
+        if let Some(visibility) = request.visibility {
+            assignments.push("visibility = ?");
+            bind_values.push(visibility.as_str().to_string());
+        }
+
+        match entry_date {
+            Some(Some(date)) => {
+                assignments.push("entry_date = ?");
+                bind_values.push(date.to_string());
+            }
+            Some(None) => assignments.push("entry_date = NULL"),
+            None => {}
+        }
+
         let tags_json = request
             .tags
             .as_ref()
             .map(|t| serde_json::to_string(t).unwrap());
         if let Some(ref tags_str) = tags_json {
-            query_parts.push("tags = ?");
+            assignments.push("tags = ?");
             bind_values.push(tags_str.clone());
-            _has_updates = true;
         }
 
-        query_parts.push("WHERE id = ?");
-        bind_values.push(request.id.clone());
+        if assignments.is_empty() {
+            return Ok(Some(existing));
+        }
 
-        let query_str = query_parts.join(", ").replace(", WHERE", " WHERE");
+        let query_str = format!("UPDATE entries SET {} WHERE id = ?", assignments.join(", "));
+        bind_values.push(request.id.clone());
 
         let mut query = sqlx::query(&query_str);
         for value in &bind_values {
             query = query.bind(value);
         }
 
-        query.execute(&self.pool).await?;
+        // The snapshot, the edit, and the index update land together or
+        // not at all
+        let mut tx = self.pool.begin().await?;
+        if versioned_changed {
+            self.snapshot_entry(&mut *tx, &existing).await?;
+        }
+        if content_changed {
+            self.remove_entry_fts(&mut *tx, &request.id).await?;
+        }
 
-        // Update FTS if title or body changed
-        if request.title.is_some() || request.body.is_some() {
-            if let Some(entry) = self.get_entry(&request.id).await? {
-                sqlx::query("UPDATE entry_fts SET title = ?, body = ? WHERE id = ?")
-                    .bind(&entry.title)
-                    .bind(&entry.body)
-                    .bind(&request.id)
-                    .execute(&self.pool)
-                    .await?;
-            }
+        query.execute(&mut *tx).await?;
+
+        if content_changed {
+            self.index_entry_fts(&mut *tx, &request.id).await?;
+        }
+        // Chunks feed AI features, which hidden entries are kept out of
+        let hidden = request.visibility.is_some_and(|v| v != Visibility::Normal);
+        if content_changed || hidden {
+            self.clear_entry_chunks(&mut *tx, &request.id).await?;
         }
+        tx.commit().await?;
 
         self.get_entry(&request.id).await
     }
 
-    pub async fn delete_entry(&self, id: &str) -> Result<bool> {
-        let result = sqlx::query("DELETE FROM entries WHERE id = ?")
+    // Entries dated in the future (beyond a day of slack) or implausibly
+    // far in the past, typically left behind by a wrong system clock
+    pub async fn find_anomalous_dates(&self, user_id: &str, tz: Tz) -> Result<Vec<DateAnomaly>> {
+        let now = Utc::now();
+        let entries = self.get_entries(user_id, tz).await?;
+        Ok(entries
+            .into_iter()
+            .filter_map(|entry| {
+                date_anomaly(entry.created_at, now).map(|kind| DateAnomaly {
+                    entry: EntrySummary::from(entry),
+                    kind,
+                })
+            })
+            .collect())
+    }
+
+    // Moves an entry to `created_at`, which must itself be a plausible date.
+    // updated_at is pulled back too if the fix would leave it in the future.
+    pub async fn set_entry_created_at(
+        &self,
+        user_id: &str,
+        id: &str,
+        created_at: DateTime<Utc>,
+    ) -> Result<Option<JournalEntry>> {
+        let now = Utc::now();
+        if let Some(kind) = date_anomaly(created_at, now) {
+            return Err(anyhow!("{}", date_anomaly_message(created_at, kind)));
+        }
+
+        let Some(existing) = self.get_entry(id).await? else {
+            return Ok(None);
+        };
+        if existing.user_id != user_id || existing.deleted_at.is_some() {
+            return Ok(None);
+        }
+        let updated_at = if date_anomaly(existing.updated_at, now).is_some() {
+            created_at
+        } else {
+            existing.updated_at.max(created_at)
+        };
+
+        sqlx::query(
+            "UPDATE entries SET created_at = ?, updated_at = ? WHERE id = ? AND deleted_at IS NULL",
+        )
+        .bind(created_at.to_rfc3339())
+        .bind(updated_at.to_rfc3339())
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        self.get_entry(id).await
+    }
+
+    // Appends `text` to an entry's body in a single UPDATE, so concurrent
+    // appends can't overwrite each other. The separator is skipped when the
+    // body is empty. Returns `None` if the entry doesn't exist or is in the
+    // trash.
+    pub async fn append_to_entry(
+        &self,
+        id: &str,
+        text: &str,
+        separator: &str,
+    ) -> Result<Option<JournalEntry>> {
+        let mut tx = self.pool.begin().await?;
+
+        self.remove_entry_fts(&mut *tx, id).await?;
+
+        let result = sqlx::query(
+            "UPDATE entries SET body = CASE WHEN body = '' THEN ? ELSE body || ? || ? END, updated_at = ? WHERE id = ? AND deleted_at IS NULL",
+        )
+        .bind(text)
+        .bind(separator)
+        .bind(text)
+        .bind(Utc::now().to_rfc3339())
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        let body: String = sqlx::query_scalar("SELECT body FROM entries WHERE id = ?")
             .bind(id)
-            .execute(&self.pool)
+            .fetch_one(&mut *tx)
             .await?;
-
-        // Delete from FTS
-        sqlx::query("DELETE FROM entry_fts WHERE id = ?")
+        sqlx::query("UPDATE entries SET word_count = ? WHERE id = ?")
+            .bind(word_count(&body) as i64)
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await?;
 
-        Ok(result.rows_affected() > 0)
+        self.index_entry_fts(&mut *tx, id).await?;
+        self.clear_entry_chunks(&mut *tx, id).await?;
+        tx.commit().await?;
+
+        self.get_entry(id).await
     }
 
-    pub async fn search_entries(
+    // Folds the `from_ids` entries into `into_id`: bodies are concatenated in
+    // the order given, the earliest created_at is kept, and the merged-from
+    // entries are moved to the trash. Every id must belong to `user_id`.
+    pub async fn merge_entries(
         &self,
         user_id: &str,
-        request: SearchRequest,
-    ) -> Result<Vec<JournalEntry>> {
-        let limit = request.limit.unwrap_or(50);
+        into_id: &str,
+        from_ids: &[String],
+        separator: &str,
+        union_tags: bool,
+    ) -> Result<JournalEntry> {
+        if from_ids.is_empty() {
+            return Err(anyhow!("No entries to merge"));
+        }
+        for (i, id) in from_ids.iter().enumerate() {
+            if id == into_id || from_ids[i + 1..].contains(id) {
+                return Err(anyhow!("Entry {} is listed more than once", id));
+            }
+        }
 
-        // Try FTS5 search first, fall back to simple LIKE search if FTS fails
-        let phrase_query = format!("\"{}\"", request.query.replace('"', "\""));
+        let mut tx = self.pool.begin().await?;
 
-        // First try FTS5 search
-        let fts_rows = sqlx::query(
-            r#"
-            SELECT e.id, e.user_id, e.title, e.body, e.created_at, e.updated_at, e.mood, e.tags
-            FROM entries e
-            INNER JOIN entry_fts fts ON e.id = fts.id
-            WHERE e.user_id = ? AND entry_fts MATCH ?
-            ORDER BY bm25(entry_fts)
-            LIMIT ?
-            "#,
-        )
-        .bind(user_id)
-        .bind(&phrase_query)
-        .bind(limit)
-        .fetch_all(&self.pool)
-        .await;
+        let mut entries = Vec::new();
+        for id in std::iter::once(into_id).chain(from_ids.iter().map(String::as_str)) {
+            let row = sqlx::query(&format!(
+                "SELECT {} FROM entries e WHERE e.id = ? AND e.user_id = ? AND e.deleted_at IS NULL",
+                ENTRY_COLUMNS
+            ))
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| anyhow!("Entry {} not found", id))?;
+            entries.push(self.row_to_entry(row)?);
+        }
 
-        let rows = match fts_rows {
-            Ok(rows) if !rows.is_empty() => rows,
-            _ => {
-                // Fallback to simple LIKE search
-                let like_query = format!("%{}%", request.query);
-                sqlx::query(
-                    r#"
-                    SELECT id, user_id, title, body, created_at, updated_at, mood, tags
-                    FROM entries
-                    WHERE user_id = ? AND (title LIKE ? OR body LIKE ?)
-                    ORDER BY created_at DESC
-                    LIMIT ?
-                    "#,
-                )
-                .bind(user_id)
-                .bind(&like_query)
-                .bind(&like_query)
-                .bind(limit)
-                .fetch_all(&self.pool)
-                .await?
+        let body = entries
+            .iter()
+            .map(|e| e.body.as_str())
+            .filter(|b| !b.is_empty())
+            .collect::<Vec<_>>()
+            .join(separator);
+        let created_at = entries.iter().map(|e| e.created_at).min().unwrap();
+
+        let mut tags = entries[0].tags.clone();
+        if union_tags {
+            let mut merged: Vec<String> = Vec::new();
+            for tag in entries.iter().filter_map(|e| e.tags.as_ref()).flatten() {
+                if !merged.contains(tag) {
+                    merged.push(tag.clone());
+                }
             }
-        };
+            if !merged.is_empty() {
+                tags = Some(merged);
+            }
+        }
+        let tags_json = tags.as_ref().map(serde_json::to_string).transpose()?;
+        // Merged text is as sensitive as the most sensitive part of it
+        let visibility = entries.iter().map(|e| e.visibility).max().unwrap();
 
-        let mut entries = Vec::new();
-        for row in rows {
-            entries.push(self.row_to_entry(row)?);
+        self.snapshot_entry(&mut *tx, &entries[0]).await?;
+
+        // Trashed entries keep their index rows, so only the merged entry's
+        // text changes in the index
+        self.remove_entry_fts(&mut *tx, into_id).await?;
+
+        sqlx::query(
+            "UPDATE entries SET body = ?, word_count = ?, tags = ?, visibility = ?, created_at = ?, updated_at = ? WHERE id = ?",
+        )
+        .bind(&body)
+        .bind(word_count(&body) as i64)
+        .bind(&tags_json)
+        .bind(visibility.as_str())
+        .bind(created_at.to_rfc3339())
+        .bind(Utc::now().to_rfc3339())
+        .bind(into_id)
+        .execute(&mut *tx)
+        .await?;
+
+        let now = Utc::now().to_rfc3339();
+        for id in from_ids {
+            sqlx::query("UPDATE entries SET deleted_at = ?, pin_order = NULL WHERE id = ?")
+                .bind(&now)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
         }
 
-        Ok(entries)
+        self.index_entry_fts(&mut *tx, into_id).await?;
+        self.clear_entry_chunks(&mut *tx, into_id).await?;
+        tx.commit().await?;
+
+        self.get_entry(into_id)
+            .await?
+            .ok_or_else(|| anyhow!("Entry {} not found", into_id))
     }
 
-    // --- Chat persistence ---
-    pub async fn create_chat_message(
+    // Cuts an entry's body at `offsets` into consecutive parts. Each part
+    // becomes an entry with the original's mood, tags, visibility, and
+    // created_at. With `keep_original` the first part stays in the original
+    // entry; otherwise the original is moved to the trash. Returns the
+    // resulting entries in body order.
+    pub async fn split_entry(
         &self,
         user_id: &str,
-        content: &str,
-        is_user: bool,
-    ) -> Result<String> {
-        let id = Uuid::new_v4().to_string();
+        id: &str,
+        offsets: &[usize],
+        keep_original: bool,
+    ) -> Result<Vec<JournalEntry>> {
+        if offsets.is_empty() {
+            return Err(anyhow!("No split offsets given"));
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM entries e WHERE e.id = ? AND e.user_id = ? AND e.deleted_at IS NULL",
+            ENTRY_COLUMNS
+        ))
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| anyhow!("Entry {} not found", id))?;
+        let original = self.row_to_entry(row)?;
+
+        // Offsets count characters, so each one is mapped to the byte where
+        // that character starts before slicing
+        let mut parts = Vec::new();
+        let mut start = 0;
+        let mut prev = 0;
+        for &offset in offsets {
+            let byte = (offset > prev)
+                .then(|| original.body.char_indices().nth(offset))
+                .flatten()
+                .map(|(i, _)| i)
+                .ok_or_else(|| {
+                    anyhow!("Split offset {} is out of bounds or out of order", offset)
+                })?;
+            parts.push(&original.body[start..byte]);
+            start = byte;
+            prev = offset;
+        }
+        parts.push(&original.body[start..]);
+
         let now = Utc::now().to_rfc3339();
+        let tags_json = original
+            .tags
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
 
-        sqlx::query(
-            "INSERT INTO chat_messages (id, user_id, content, is_user, created_at) VALUES (?, ?, ?, ?, ?)"
+        let mut new_parts = parts.iter().enumerate();
+        let mut ids = Vec::new();
+        if keep_original {
+            let (_, first) = new_parts.next().unwrap();
+            self.snapshot_entry(&mut *tx, &original).await?;
+            self.remove_entry_fts(&mut *tx, id).await?;
+            sqlx::query("UPDATE entries SET body = ?, word_count = ?, updated_at = ? WHERE id = ?")
+                .bind(first.trim())
+                .bind(word_count(first) as i64)
+                .bind(&now)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            self.index_entry_fts(&mut *tx, id).await?;
+            self.clear_entry_chunks(&mut *tx, id).await?;
+            ids.push(id.to_string());
+        } else {
+            sqlx::query("UPDATE entries SET deleted_at = ?, pin_order = NULL WHERE id = ?")
+                .bind(&now)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        for (index, part) in new_parts {
+            let part_id = Uuid::new_v4().to_string();
+            sqlx::query(
+                "INSERT INTO entries (id, user_id, title, body, created_at, updated_at, entry_date, mood, mood_intensity, tags, visibility, word_count) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+            )
+            .bind(&part_id)
+            .bind(user_id)
+            .bind(format!("{} ({})", original.title, index + 1))
+            .bind(part.trim())
+            .bind(original.created_at.to_rfc3339())
+            .bind(&now)
+            .bind(original.entry_date)
+            .bind(&original.mood)
+            .bind(original.mood_intensity)
+            .bind(&tags_json)
+            .bind(original.visibility.as_str())
+            .bind(word_count(part) as i64)
+            .execute(&mut *tx)
+            .await?;
+            self.index_entry_fts(&mut *tx, &part_id).await?;
+            ids.push(part_id);
+        }
+
+        tx.commit().await?;
+
+        let mut entries = Vec::new();
+        for id in &ids {
+            if let Some(entry) = self.get_entry(id).await? {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    // Moves an entry to the trash. It keeps its search index rows, since
+    // entry_fts must mirror the entries table for rebuilds and the audit's
+    // integrity check; trashed entries are filtered out of queries instead.
+    // A trashed entry also loses its pin.
+    pub async fn delete_entry(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE entries SET deleted_at = ?, pin_order = NULL WHERE id = ? AND deleted_at IS NULL",
         )
-        .bind(&id)
-        .bind(user_id)
-        .bind(content)
-        .bind(is_user)
-        .bind(&now)
+        .bind(Utc::now().to_rfc3339())
+        .bind(id)
         .execute(&self.pool)
         .await?;
 
-        Ok(id)
+        Ok(result.rows_affected() > 0)
     }
 
-    pub async fn get_chat_messages(
-        &self,
-        user_id: &str,
-        limit: Option<i32>,
-    ) -> Result<Vec<ChatMessage>> {
-        let limit = limit.unwrap_or(50);
-        let rows = sqlx::query(
-            "SELECT id, user_id, content, is_user, created_at FROM chat_messages WHERE user_id = ? ORDER BY created_at DESC LIMIT ?"
+    // Takes an entry back out of the trash. Returns None when it isn't there.
+    pub async fn restore_entry(&self, user_id: &str, id: &str) -> Result<Option<JournalEntry>> {
+        let result = sqlx::query(
+            "UPDATE entries SET deleted_at = NULL WHERE id = ? AND user_id = ? AND deleted_at IS NOT NULL",
         )
+        .bind(id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+        self.get_entry(id).await
+    }
+
+    // Entries in the trash, most recently deleted first
+    pub async fn get_trashed_entries(&self, user_id: &str) -> Result<Vec<JournalEntry>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM entries e WHERE e.user_id = ? AND e.deleted_at IS NOT NULL ORDER BY e.deleted_at DESC, e.id DESC",
+            ENTRY_COLUMNS
+        ))
         .bind(user_id)
-        .bind(limit)
         .fetch_all(&self.pool)
         .await?;
 
-        let mut messages = Vec::new();
+        let mut entries = Vec::new();
         for row in rows {
-            messages.push(ChatMessage {
-                id: row.try_get("id")?,
-                user_id: row.try_get("user_id")?,
-                content: row.try_get("content")?,
-                is_user: row.try_get("is_user")?,
-                created_at: row.try_get("created_at")?,
-            });
+            entries.push(self.row_to_entry(row)?);
         }
-
-        // Reverse to get chronological order
-        messages.reverse();
-        Ok(messages)
+        Ok(entries)
     }
 
-    fn row_to_entry(&self, row: SqliteRow) -> Result<JournalEntry> {
-        let tags_str: Option<String> = row.try_get("tags")?;
-        let tags = tags_str.and_then(|s| serde_json::from_str(&s).ok());
+    // Permanently deletes one of the user's entries, trashed or not, with
+    // its search index rows, custom fields, saved versions, and chunks
+    pub async fn purge_entry(&self, user_id: &str, id: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
 
-        Ok(JournalEntry {
-            id: row.try_get("id")?,
+        let owned = sqlx::query("SELECT 1 FROM entries WHERE id = ? AND user_id = ?")
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if !owned {
+            return Ok(false);
+        }
+
+        // Delete from FTS while the content row still exists
+        self.remove_entry_fts(&mut *tx, id).await?;
+
+        for table in ["entry_fields", "entry_versions", "chunks"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE entry_id = ?", table))
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        let result = sqlx::query("DELETE FROM entries WHERE id = ? AND user_id = ?")
+            .bind(id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // entry_fts is an external-content table keyed on the entries rowid, so
+    // index rows must be written from, and removed against, the current
+    // contents of `entries`. They take an executor so they can run inside a
+    // transaction alongside the write they accompany.
+    async fn index_entry_fts<'e, E>(&self, executor: E, id: &str) -> Result<()>
+    where
+        E: Executor<'e, Database = Sqlite>,
+    {
+        if !self.fts_enabled {
+            return Ok(());
+        }
+        sqlx::query(
+            "INSERT INTO entry_fts (rowid, id, title, body) SELECT rowid, id, title, body FROM entries WHERE id = ?",
+        )
+        .bind(id)
+        .execute(executor)
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_entry_fts<'e, E>(&self, executor: E, id: &str) -> Result<()>
+    where
+        E: Executor<'e, Database = Sqlite>,
+    {
+        if !self.fts_enabled {
+            return Ok(());
+        }
+        sqlx::query(
+            "INSERT INTO entry_fts (entry_fts, rowid, id, title, body) SELECT 'delete', rowid, id, title, body FROM entries WHERE id = ?",
+        )
+        .bind(id)
+        .execute(executor)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn search_entries(
+        &self,
+        user_id: &str,
+        request: SearchRequest,
+    ) -> Result<Vec<JournalEntry>> {
+        let limit = request.limit.unwrap_or(50);
+        let query = request.query.trim();
+        if request.range_is_inverted() {
+            return Ok(Vec::new());
+        }
+        if query.is_empty() {
+            if !request.recent_if_empty.unwrap_or(false) {
+                return Ok(Vec::new());
+            }
+            // Most recently written first, whatever day they're about
+            let (from, to) = request.range_bounds();
+            let rows = sqlx::query(&format!(
+                "SELECT {} FROM entries e WHERE e.user_id = ? AND e.deleted_at IS NULL AND {} ORDER BY e.created_at DESC, e.id DESC LIMIT ?",
+                ENTRY_COLUMNS, CREATED_RANGE_FILTER
+            ))
+            .bind(user_id)
+            .bind(&from)
+            .bind(&from)
+            .bind(&to)
+            .bind(&to)
+            .bind(limit.max(0))
+            .fetch_all(&self.pool)
+            .await?;
+
+            let mut entries = Vec::new();
+            for row in rows {
+                entries.push(self.row_to_entry(row)?);
+            }
+            return Ok(entries);
+        }
+        let (from, to) = request.range_bounds();
+
+        let recency_weight = request.recency_weight.unwrap_or(0.0).clamp(0.0, 1.0);
+        let edit_recency_weight = request.edit_recency_weight.unwrap_or(0.0).clamp(0.0, 1.0);
+        let blending = recency_weight > 0.0 || edit_recency_weight > 0.0;
+        // Blending needs every match scored before truncating to `limit`
+        let fts_limit = if blending { -1 } else { limit };
+        let title_weight = request
+            .title_weight
+            .filter(|w| w.is_finite() && *w >= 0.0)
+            .unwrap_or(DEFAULT_TITLE_WEIGHT);
+
+        let fields = request.fields.unwrap_or_default();
+
+        // FTS5 when it is available and the query has something to match;
+        // an FTS error sends the search down the LIKE path instead
+        let fts_query = escape_fts_query(query)
+            .filter(|_| self.fts_enabled)
+            .map(|q| fields.fts_query(&q));
+        let fts_rows = match fts_query {
+            Some(fts_query) => sqlx::query(&format!(
+                r#"
+                SELECT {}, bm25(entry_fts, 0.0, ?, 1.0) AS rank
+                FROM entries e
+                INNER JOIN entry_fts fts ON e.rowid = fts.rowid
+                WHERE e.user_id = ? AND e.deleted_at IS NULL AND entry_fts MATCH ? AND {}
+                ORDER BY rank
+                LIMIT ?
+                "#,
+                ENTRY_COLUMNS, CREATED_RANGE_FILTER
+            ))
+            .bind(title_weight)
+            .bind(user_id)
+            .bind(&fts_query)
+            .bind(&from)
+            .bind(&from)
+            .bind(&to)
+            .bind(&to)
+            .bind(fts_limit)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| log::warn!("Full-text search failed, matching text instead: {}", e))
+            .ok(),
+            None => None,
+        };
+
+        let entries = match fts_rows {
+            Some(rows) => {
+                let mut scored = Vec::new();
+                for row in rows {
+                    let rank: f64 = row.try_get("rank")?;
+                    scored.push((self.row_to_entry(row)?, rank));
+                }
+
+                if blending {
+                    blend_recency(&mut scored, recency_weight, edit_recency_weight, Utc::now());
+                    scored.truncate(limit.max(0) as usize);
+                }
+
+                scored.into_iter().map(|(entry, _)| entry).collect()
+            }
+            None => {
+                // Fallback to simple LIKE search
+                let like_query = format!("%{}%", query);
+                let like_condition = fields
+                    .columns()
+                    .iter()
+                    .map(|column| format!("{} LIKE ?", column))
+                    .collect::<Vec<_>>()
+                    .join(" OR ");
+                let sql = format!(
+                    r#"
+                    SELECT {}
+                    FROM entries e
+                    WHERE e.user_id = ? AND e.deleted_at IS NULL AND ({}) AND {}
+                    ORDER BY e.created_at DESC, e.id DESC
+                    LIMIT ?
+                    "#,
+                    ENTRY_COLUMNS, like_condition, CREATED_RANGE_FILTER
+                );
+                let mut rows_query = sqlx::query(&sql).bind(user_id);
+                for _ in fields.columns() {
+                    rows_query = rows_query.bind(&like_query);
+                }
+                let rows = rows_query
+                    .bind(&from)
+                    .bind(&from)
+                    .bind(&to)
+                    .bind(&to)
+                    .bind(limit)
+                    .fetch_all(&self.pool)
+                    .await?;
+
+                let mut entries = Vec::new();
+                for row in rows {
+                    entries.push(self.row_to_entry(row)?);
+                }
+                entries
+            }
+        };
+
+        Ok(entries)
+    }
+
+    // `search_entries` plus a count of everything the query matched. The
+    // count is a second query, so plain searches don't pay for it.
+    pub async fn search_entries_with_total(
+        &self,
+        user_id: &str,
+        request: SearchRequest,
+    ) -> Result<SearchPage> {
+        let total_matches = self.count_search_matches(user_id, &request).await?;
+        let results = self.search_entries(user_id, request).await?;
+        Ok(SearchPage {
+            truncated: total_matches > results.len() as i64,
+            results,
+            total_matches,
+        })
+    }
+
+    // Counts what `search_entries` would match without a limit, taking the
+    // same FTS-then-LIKE path
+    async fn count_search_matches(&self, user_id: &str, request: &SearchRequest) -> Result<i64> {
+        let query = request.query.trim();
+        if request.range_is_inverted() {
+            return Ok(0);
+        }
+        let (from, to) = request.range_bounds();
+        if query.is_empty() {
+            if !request.recent_if_empty.unwrap_or(false) {
+                return Ok(0);
+            }
+            let count = sqlx::query_scalar(&format!(
+                "SELECT COUNT(*) FROM entries e WHERE e.user_id = ? AND e.deleted_at IS NULL AND {}",
+                CREATED_RANGE_FILTER
+            ))
+            .bind(user_id)
+            .bind(&from)
+            .bind(&from)
+            .bind(&to)
+            .bind(&to)
+            .fetch_one(&self.pool)
+            .await?;
+            return Ok(count);
+        }
+
+        let fields = request.fields.unwrap_or_default();
+        let fts_query = escape_fts_query(query)
+            .filter(|_| self.fts_enabled)
+            .map(|q| fields.fts_query(&q));
+        if let Some(fts_query) = fts_query {
+            let fts_count: std::result::Result<i64, _> = sqlx::query_scalar(&format!(
+                r#"
+                SELECT COUNT(*)
+                FROM entries e
+                INNER JOIN entry_fts fts ON e.rowid = fts.rowid
+                WHERE e.user_id = ? AND e.deleted_at IS NULL AND entry_fts MATCH ? AND {}
+                "#,
+                CREATED_RANGE_FILTER
+            ))
+            .bind(user_id)
+            .bind(&fts_query)
+            .bind(&from)
+            .bind(&from)
+            .bind(&to)
+            .bind(&to)
+            .fetch_one(&self.pool)
+            .await;
+            match fts_count {
+                Ok(count) => return Ok(count),
+                Err(e) => log::warn!("Full-text count failed, matching text instead: {}", e),
+            }
+        }
+
+        let like_query = format!("%{}%", query);
+        let like_condition = fields
+            .columns()
+            .iter()
+            .map(|column| format!("{} LIKE ?", column))
+            .collect::<Vec<_>>()
+            .join(" OR ");
+        let sql = format!(
+            "SELECT COUNT(*) FROM entries e WHERE e.user_id = ? AND e.deleted_at IS NULL AND ({}) AND {}",
+            like_condition, CREATED_RANGE_FILTER
+        );
+        let mut count_query = sqlx::query_scalar(&sql).bind(user_id);
+        for _ in fields.columns() {
+            count_query = count_query.bind(&like_query);
+        }
+        Ok(count_query
+            .bind(&from)
+            .bind(&from)
+            .bind(&to)
+            .bind(&to)
+            .fetch_one(&self.pool)
+            .await?)
+    }
+
+    // Cheap "have I written about this?" check: counts phrase matches whose
+    // relevance (negated bm25, higher is better) is at least `min_score`,
+    // and returns the best one. Only ids and scores are read for the count.
+    pub async fn contains_topic(
+        &self,
+        user_id: &str,
+        query: &str,
+        min_score: f64,
+    ) -> Result<TopicCheck> {
+        let query = query.trim();
+        let mut check = TopicCheck {
+            found: false,
+            count: 0,
+            top_source: None,
+        };
+        if query.is_empty() {
+            return Ok(check);
+        }
+        // Relevance scores only exist with FTS5
+        self.require_fts()?;
+
+        let rows = sqlx::query(
+            r#"
+            SELECT e.id, -bm25(entry_fts, 0.0, ?, 1.0) AS score
+            FROM entries e
+            INNER JOIN entry_fts fts ON e.rowid = fts.rowid
+            WHERE e.user_id = ? AND e.deleted_at IS NULL AND entry_fts MATCH ?
+            ORDER BY score DESC
+            "#,
+        )
+        .bind(DEFAULT_TITLE_WEIGHT)
+        .bind(user_id)
+        .bind(fts_phrase(query))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut top_id: Option<String> = None;
+        for row in rows {
+            let score: f64 = row.try_get("score")?;
+            if score < min_score {
+                break;
+            }
+            if top_id.is_none() {
+                top_id = Some(row.try_get("id")?);
+            }
+            check.count += 1;
+        }
+
+        if let Some(id) = top_id {
+            check.found = true;
+            check.top_source = self.get_entry(&id).await?.map(EntrySummary::from);
+        }
+        Ok(check)
+    }
+
+    // Finds entries related to an arbitrary passage of text. There are no
+    // stored embeddings to compare against, so this falls back to keyword
+    // retrieval: the passage's distinctive terms are OR-ed together and
+    // matches are ranked by bm25.
+    pub async fn search_by_text(
+        &self,
+        user_id: &str,
+        text: &str,
+        top_k: i32,
+    ) -> Result<Vec<JournalEntry>> {
+        self.require_fts()?;
+        let terms = significant_terms(text, MAX_SIMILARITY_TERMS);
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let match_query = terms
+            .iter()
+            .map(|term| format!("\"{}\"", term))
+            .collect::<Vec<_>>()
+            .join(" OR ");
+
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {}
+            FROM entries e
+            INNER JOIN entry_fts fts ON e.rowid = fts.rowid
+            WHERE e.user_id = ? AND e.deleted_at IS NULL AND entry_fts MATCH ?
+            ORDER BY bm25(entry_fts)
+            LIMIT ?
+            "#,
+            ENTRY_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(&match_query)
+        .bind(top_k)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(self.row_to_entry(row)?);
+        }
+
+        Ok(entries)
+    }
+
+    // Entries tagged `tag`, newest first. Tags are compared trimmed and
+    // case-insensitively, like list_tags. The match runs in SQL over the
+    // decoded JSON array (json_each), so "work" doesn't match "homework" the
+    // way a LIKE on the raw column would; entries with NULL or malformed tags
+    // simply don't match.
+    pub async fn get_entries_by_tag(
+        &self,
+        user_id: &str,
+        tz: Tz,
+        tag: &str,
+    ) -> Result<Vec<JournalEntry>> {
+        let tag = tag.trim();
+        if tag.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {}
+            FROM entries e
+            WHERE e.user_id = ? AND e.deleted_at IS NULL
+              AND EXISTS (
+                  SELECT 1
+                  FROM json_each(CASE WHEN json_valid(e.tags) THEN e.tags ELSE '[]' END) t
+                  WHERE t.type = 'text' AND lower(trim(t.value)) = lower(?)
+              )
+            "#,
+            ENTRY_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(tag)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(self.row_to_entry(row)?);
+        }
+        sort_newest_first(&mut entries, tz);
+        Ok(entries)
+    }
+
+    // Sets the visibility of every entry tagged `tag` (compared trimmed and
+    // case-insensitively, like list_tags) in a single UPDATE. Returns the
+    // ids of the entries whose visibility changed.
+    pub async fn set_visibility_by_tag(
+        &self,
+        user_id: &str,
+        tag: &str,
+        visibility: Visibility,
+    ) -> Result<Vec<String>> {
+        let tag = tag.trim();
+        if tag.is_empty() {
+            return Err(anyhow!("Tag must not be empty"));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let ids: Vec<String> = sqlx::query_scalar(
+            r#"
+            UPDATE entries SET visibility = ?
+            WHERE user_id = ? AND deleted_at IS NULL AND visibility != ?
+              AND EXISTS (
+                  SELECT 1
+                  FROM json_each(CASE WHEN json_valid(entries.tags) THEN entries.tags ELSE '[]' END) t
+                  WHERE t.type = 'text' AND lower(trim(t.value)) = lower(?)
+              )
+            RETURNING id
+            "#,
+        )
+        .bind(visibility.as_str())
+        .bind(user_id)
+        .bind(visibility.as_str())
+        .bind(tag)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        // Hidden entries are kept out of AI features, chunks included
+        if visibility != Visibility::Normal {
+            for id in &ids {
+                self.clear_entry_chunks(&mut *tx, id).await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(ids)
+    }
+
+    // Tags with the number of entries using each, most used first. Tags are
+    // compared trimmed and case-insensitively, so "Work" and "work " count
+    // as one tag.
+    pub async fn list_tags(&self, user_id: &str, min_count: i64) -> Result<Vec<TagCount>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT lower(trim(t.value)) AS tag, COUNT(DISTINCT e.id) AS count
+            FROM entries e,
+                 json_each(CASE WHEN json_valid(e.tags) THEN e.tags ELSE '[]' END) t
+            WHERE e.user_id = ? AND e.deleted_at IS NULL AND t.type = 'text' AND trim(t.value) != ''
+            GROUP BY lower(trim(t.value))
+            HAVING COUNT(DISTINCT e.id) >= ?
+            ORDER BY count DESC, tag ASC
+            "#,
+        )
+        .bind(user_id)
+        .bind(min_count)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut tags = Vec::new();
+        for row in rows {
+            tags.push(TagCount {
+                tag: row.try_get("tag")?,
+                count: row.try_get("count")?,
+            });
+        }
+        Ok(tags)
+    }
+
+    // Like list_tags, but tags are kept exactly as entered, so "Work" and
+    // "work" are counted separately. Blank tags are skipped.
+    pub async fn get_tag_counts(&self, user_id: &str) -> Result<Vec<TagCount>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT t.value AS tag, COUNT(DISTINCT e.id) AS count
+            FROM entries e,
+                 json_each(CASE WHEN json_valid(e.tags) THEN e.tags ELSE '[]' END) t
+            WHERE e.user_id = ? AND e.deleted_at IS NULL AND t.type = 'text' AND trim(t.value) != ''
+            GROUP BY t.value
+            ORDER BY count DESC, tag ASC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut tags = Vec::new();
+        for row in rows {
+            tags.push(TagCount {
+                tag: row.try_get("tag")?,
+                count: row.try_get("count")?,
+            });
+        }
+        Ok(tags)
+    }
+
+    // The user's entries created within the range, oldest first; either
+    // bound may be left open
+    pub async fn get_entries_created_between(
+        &self,
+        user_id: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<JournalEntry>> {
+        if matches!((from, to), (Some(from), Some(to)) if from > to) {
+            return Ok(Vec::new());
+        }
+        let from = from.map(|from| from.to_rfc3339());
+        let to = to.map(|to| to.to_rfc3339());
+
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM entries e WHERE e.user_id = ? AND e.deleted_at IS NULL AND {} ORDER BY e.created_at, e.id",
+            ENTRY_COLUMNS, CREATED_RANGE_FILTER
+        ))
+        .bind(user_id)
+        .bind(&from)
+        .bind(&from)
+        .bind(&to)
+        .bind(&to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(self.row_to_entry(row)?);
+        }
+        Ok(entries)
+    }
+
+    // Pairs of tags used on the same entry, most frequent first. Tags are
+    // normalized the same way as in `list_tags`.
+    pub async fn get_tag_cooccurrence(
+        &self,
+        user_id: &str,
+        min_count: i64,
+    ) -> Result<Vec<TagPair>> {
+        let rows = sqlx::query(
+            r#"
+            WITH entry_tags AS (
+                SELECT DISTINCT e.id AS entry_id, lower(trim(t.value)) AS tag
+                FROM entries e,
+                     json_each(CASE WHEN json_valid(e.tags) THEN e.tags ELSE '[]' END) t
+                WHERE e.user_id = ? AND e.deleted_at IS NULL AND t.type = 'text' AND trim(t.value) != ''
+            )
+            SELECT a.tag AS tag_a, b.tag AS tag_b, COUNT(*) AS count
+            FROM entry_tags a
+            INNER JOIN entry_tags b ON a.entry_id = b.entry_id AND a.tag < b.tag
+            GROUP BY a.tag, b.tag
+            HAVING COUNT(*) >= ?
+            ORDER BY count DESC, tag_a ASC, tag_b ASC
+            "#,
+        )
+        .bind(user_id)
+        .bind(min_count)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut pairs = Vec::new();
+        for row in rows {
+            pairs.push(TagPair {
+                tag_a: row.try_get("tag_a")?,
+                tag_b: row.try_get("tag_b")?,
+                count: row.try_get("count")?,
+            });
+        }
+        Ok(pairs)
+    }
+
+    // Pins an entry after the existing pins. Returns `None` if the entry
+    // doesn't exist; pinning an already pinned entry leaves it in place.
+    pub async fn pin_entry(
+        &self,
+        user_id: &str,
+        id: &str,
+        max_pins: Option<u32>,
+    ) -> Result<Option<JournalEntry>> {
+        let mut tx = self.pool.begin().await?;
+
+        let pin_order: Option<Option<i64>> = sqlx::query_scalar(
+            "SELECT pin_order FROM entries WHERE id = ? AND user_id = ? AND deleted_at IS NULL",
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        match pin_order {
+            None => return Ok(None),
+            Some(Some(_)) => {}
+            Some(None) => {
+                let (pinned, last): (i64, Option<i64>) = sqlx::query_as(
+                    "SELECT COUNT(*), MAX(pin_order) FROM entries WHERE user_id = ? AND pin_order IS NOT NULL",
+                )
+                .bind(user_id)
+                .fetch_one(&mut *tx)
+                .await?;
+                if let Some(max) = max_pins {
+                    if pinned >= i64::from(max) {
+                        return Err(anyhow!(
+                            "Pin limit of {} reached; unpin an entry first",
+                            max
+                        ));
+                    }
+                }
+
+                sqlx::query("UPDATE entries SET pin_order = ? WHERE id = ?")
+                    .bind(last.unwrap_or(0) + 1)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+
+        tx.commit().await?;
+        self.get_entry(id).await
+    }
+
+    pub async fn unpin_entry(&self, user_id: &str, id: &str) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE entries SET pin_order = NULL WHERE id = ? AND user_id = ? AND pin_order IS NOT NULL",
+        )
+        .bind(id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Pinned entries in their display order
+    pub async fn get_pinned_entries(&self, user_id: &str) -> Result<Vec<JournalEntry>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM entries e WHERE e.user_id = ? AND e.pin_order IS NOT NULL ORDER BY e.pin_order, e.id",
+            ENTRY_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(self.row_to_entry(row)?);
+        }
+        Ok(entries)
+    }
+
+    // Puts `ids` first, in the given order; pinned entries left out keep
+    // their relative order after them. Every id must be a pinned entry.
+    pub async fn reorder_pins(&self, user_id: &str, ids: &[String]) -> Result<Vec<JournalEntry>> {
+        let mut tx = self.pool.begin().await?;
+
+        let pinned: Vec<String> = sqlx::query_scalar(
+            "SELECT id FROM entries WHERE user_id = ? AND pin_order IS NOT NULL ORDER BY pin_order, id",
+        )
+        .bind(user_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for (i, id) in ids.iter().enumerate() {
+            if !pinned.contains(id) {
+                return Err(anyhow!("Entry {} is not pinned", id));
+            }
+            if ids[i + 1..].contains(id) {
+                return Err(anyhow!("Entry {} is listed more than once", id));
+            }
+        }
+
+        let rest = pinned.iter().filter(|id| !ids.contains(id));
+        for (position, id) in ids.iter().chain(rest).enumerate() {
+            sqlx::query("UPDATE entries SET pin_order = ? WHERE id = ?")
+                .bind(position as i64 + 1)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        self.get_pinned_entries(user_id).await
+    }
+
+    // The subset of `entry_ids` that must be kept out of AI features
+    pub async fn get_ai_excluded_ids(
+        &self,
+        user_id: &str,
+        entry_ids: &[String],
+    ) -> Result<Vec<String>> {
+        if entry_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let placeholders = vec!["?"; entry_ids.len()].join(", ");
+        let query_str = format!(
+            "SELECT id FROM entries WHERE user_id = ? AND (visibility != 'normal' OR deleted_at IS NOT NULL) AND id IN ({})",
+            placeholders
+        );
+
+        let mut query = sqlx::query(&query_str).bind(user_id);
+        for id in entry_ids {
+            query = query.bind(id);
+        }
+        let rows = query.fetch_all(&self.pool).await?;
+
+        rows.iter().map(|row| Ok(row.try_get("id")?)).collect()
+    }
+
+    // Counts one retrieval for each of the given entries with a single UPDATE.
+    // Ids that don't belong to the user are ignored.
+    pub async fn record_retrievals(&self, user_id: &str, entry_ids: &[String]) -> Result<()> {
+        if entry_ids.is_empty() {
+            return Ok(());
+        }
+
+        let placeholders = vec!["?"; entry_ids.len()].join(", ");
+        let query_str = format!(
+            "UPDATE entries SET retrieval_count = retrieval_count + 1, last_retrieved_at = ? WHERE user_id = ? AND id IN ({})",
+            placeholders
+        );
+
+        let mut query = sqlx::query(&query_str)
+            .bind(Utc::now().to_rfc3339())
+            .bind(user_id);
+        for id in entry_ids {
+            query = query.bind(id);
+        }
+        query.execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    pub async fn get_most_referenced_entries(
+        &self,
+        user_id: &str,
+        limit: i32,
+    ) -> Result<Vec<ReferencedEntry>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {}, e.retrieval_count, e.last_retrieved_at
+            FROM entries e
+            WHERE e.user_id = ? AND e.deleted_at IS NULL AND e.retrieval_count > 0
+            ORDER BY e.retrieval_count DESC, e.last_retrieved_at DESC
+            LIMIT ?
+            "#,
+            ENTRY_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            let retrieval_count: i64 = row.try_get("retrieval_count")?;
+            let last_retrieved_at: Option<String> = row.try_get("last_retrieved_at")?;
+            entries.push(ReferencedEntry {
+                entry: self.row_to_entry(row)?,
+                retrieval_count,
+                last_retrieved_at,
+            });
+        }
+        Ok(entries)
+    }
+
+    // Sets (or replaces) a custom field on one of the user's entries.
+    // Returns false if the entry doesn't exist or is in the trash.
+    pub async fn set_entry_field(
+        &self,
+        user_id: &str,
+        entry_id: &str,
+        key: &str,
+        value: &serde_json::Value,
+        value_type: FieldType,
+    ) -> Result<bool> {
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(anyhow!("Field key must not be empty"));
+        }
+        let value = FieldValue::new(value, value_type)?;
+
+        let query = sqlx::query(
+            r#"
+            INSERT INTO entry_fields (entry_id, key, value, value_type)
+            SELECT id, ?, ?, ? FROM entries WHERE id = ? AND user_id = ? AND deleted_at IS NULL
+            ON CONFLICT (entry_id, key) DO UPDATE
+            SET value = excluded.value, value_type = excluded.value_type
+            "#,
+        )
+        .bind(key);
+        let result = value
+            .bind(query)
+            .bind(value_type.as_str())
+            .bind(entry_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Empty when the entry doesn't exist or belongs to someone else
+    pub async fn get_entry_fields(
+        &self,
+        user_id: &str,
+        entry_id: &str,
+    ) -> Result<BTreeMap<String, serde_json::Value>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT f.key, f.value, f.value_type
+            FROM entry_fields f
+            INNER JOIN entries e ON e.id = f.entry_id
+            WHERE f.entry_id = ? AND e.user_id = ?
+            "#,
+        )
+        .bind(entry_id)
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut fields = BTreeMap::new();
+        for row in rows {
+            let (key, value) = self.row_to_field(row)?;
+            fields.insert(key, value);
+        }
+        Ok(fields)
+    }
+
+    pub async fn save_entry_version(
+        &self,
+        entry_id: &str,
+        title: &str,
+        body: &str,
+        mood: Option<&str>,
+        tags: Option<&[String]>,
+    ) -> Result<EntryVersion> {
+        let version = EntryVersion {
+            id: Uuid::new_v4().to_string(),
+            entry_id: entry_id.to_string(),
+            title: title.to_string(),
+            body: body.to_string(),
+            mood: mood.map(str::to_string),
+            tags: tags.map(<[String]>::to_vec),
+            saved_at: Utc::now(),
+        };
+        self.insert_entry_version(&self.pool, &version).await?;
+        Ok(version)
+    }
+
+    // Saves an entry as it currently is, before an edit overwrites it
+    async fn snapshot_entry<'e, E>(&self, executor: E, entry: &JournalEntry) -> Result<()>
+    where
+        E: Executor<'e, Database = Sqlite>,
+    {
+        let version = EntryVersion {
+            id: Uuid::new_v4().to_string(),
+            entry_id: entry.id.clone(),
+            title: entry.title.clone(),
+            body: entry.body.clone(),
+            mood: entry.mood.clone(),
+            tags: entry.tags.clone(),
+            saved_at: Utc::now(),
+        };
+        self.insert_entry_version(executor, &version).await
+    }
+
+    async fn insert_entry_version<'e, E>(&self, executor: E, version: &EntryVersion) -> Result<()>
+    where
+        E: Executor<'e, Database = Sqlite>,
+    {
+        let tags_json = version
+            .tags
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+
+        sqlx::query(
+            "INSERT INTO entry_versions (id, entry_id, title, body, mood, tags, saved_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&version.id)
+        .bind(&version.entry_id)
+        .bind(&version.title)
+        .bind(&version.body)
+        .bind(&version.mood)
+        .bind(tags_json)
+        .bind(version.saved_at.to_rfc3339())
+        .execute(executor)
+        .await?;
+        Ok(())
+    }
+
+    // Saved versions of one of the user's entries, newest first
+    pub async fn get_entry_history(
+        &self,
+        user_id: &str,
+        entry_id: &str,
+    ) -> Result<Vec<EntryVersion>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT v.id, v.entry_id, v.title, v.body, v.mood, v.tags, v.saved_at
+            FROM entry_versions v
+            INNER JOIN entries e ON e.id = v.entry_id
+            WHERE v.entry_id = ? AND e.user_id = ?
+            ORDER BY v.saved_at DESC, v.rowid DESC
+            "#,
+        )
+        .bind(entry_id)
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(row_to_entry_version).collect()
+    }
+
+    // Splits `body` into chunks and stores them in place of any the entry
+    // already had
+    pub async fn create_text_chunks(
+        &self,
+        entry_id: &str,
+        user_id: &str,
+        body: &str,
+        options: ChunkOptions,
+    ) -> Result<Vec<TextChunk>> {
+        options.validate()?;
+        let chunks: Vec<TextChunk> = chunk_text(body, options)
+            .into_iter()
+            .enumerate()
+            .map(|(ord, text)| TextChunk {
+                id: Uuid::new_v4().to_string(),
+                entry_id: entry_id.to_string(),
+                user_id: user_id.to_string(),
+                text,
+                ord: ord as i64,
+            })
+            .collect();
+
+        let mut tx = self.pool.begin().await?;
+        self.clear_entry_chunks(&mut *tx, entry_id).await?;
+        for chunk in &chunks {
+            sqlx::query(
+                "INSERT INTO chunks (id, entry_id, user_id, text, ord) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(&chunk.id)
+            .bind(&chunk.entry_id)
+            .bind(&chunk.user_id)
+            .bind(&chunk.text)
+            .bind(chunk.ord)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(chunks)
+    }
+
+    // Chunks are cut from a particular body, so any edit to it drops them
+    // until the entry is chunked again
+    async fn clear_entry_chunks<'e, E>(&self, executor: E, entry_id: &str) -> Result<()>
+    where
+        E: Executor<'e, Database = Sqlite>,
+    {
+        sqlx::query("DELETE FROM chunks WHERE entry_id = ?")
+            .bind(entry_id)
+            .execute(executor)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_chunks_for_entry(
+        &self,
+        user_id: &str,
+        entry_id: &str,
+    ) -> Result<Vec<TextChunk>> {
+        let rows = sqlx::query(
+            "SELECT id, entry_id, user_id, text, ord FROM chunks WHERE entry_id = ? AND user_id = ? ORDER BY ord",
+        )
+        .bind(entry_id)
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut chunks = Vec::new();
+        for row in rows {
+            chunks.push(TextChunk {
+                id: row.try_get("id")?,
+                entry_id: row.try_get("entry_id")?,
+                user_id: row.try_get("user_id")?,
+                text: row.try_get("text")?,
+                ord: row.try_get("ord")?,
+            });
+        }
+        Ok(chunks)
+    }
+
+    // A saved version, if it belongs to one of the user's entries
+    pub async fn get_entry_version(
+        &self,
+        user_id: &str,
+        version_id: &str,
+    ) -> Result<Option<EntryVersion>> {
+        let row = sqlx::query(
+            r#"
+            SELECT v.id, v.entry_id, v.title, v.body, v.mood, v.tags, v.saved_at
+            FROM entry_versions v
+            INNER JOIN entries e ON e.id = v.entry_id
+            WHERE v.id = ? AND e.user_id = ?
+            "#,
+        )
+        .bind(version_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(row_to_entry_version).transpose()
+    }
+
+    // Every custom field of the user's entries, keyed by entry id
+    pub async fn get_all_entry_fields(
+        &self,
+        user_id: &str,
+    ) -> Result<HashMap<String, BTreeMap<String, serde_json::Value>>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT f.entry_id, f.key, f.value, f.value_type
+            FROM entry_fields f
+            INNER JOIN entries e ON e.id = f.entry_id
+            WHERE e.user_id = ? AND e.deleted_at IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut fields: HashMap<String, BTreeMap<String, serde_json::Value>> = HashMap::new();
+        for row in rows {
+            let entry_id: String = row.try_get("entry_id")?;
+            let (key, value) = self.row_to_field(row)?;
+            fields.entry(entry_id).or_default().insert(key, value);
+        }
+        Ok(fields)
+    }
+
+    // Entries whose field `key` compares to `value` under `op`. Only fields
+    // of the same type as `value_type` are considered.
+    pub async fn get_entries_where_field(
+        &self,
+        user_id: &str,
+        key: &str,
+        op: FieldOp,
+        value: &serde_json::Value,
+        value_type: FieldType,
+    ) -> Result<Vec<JournalEntry>> {
+        let value = FieldValue::new(value, value_type)?;
+
+        let query_str = format!(
+            r#"
+            SELECT {}
+            FROM entries e
+            INNER JOIN entry_fields f ON f.entry_id = e.id
+            WHERE e.user_id = ? AND e.deleted_at IS NULL AND f.key = ? AND f.value_type = ? AND f.value {} ?
+            ORDER BY e.created_at DESC, e.id DESC
+            "#,
+            ENTRY_COLUMNS,
+            op.as_sql()
+        );
+        let query = sqlx::query(&query_str)
+            .bind(user_id)
+            .bind(key.trim())
+            .bind(value_type.as_str());
+        let rows = value.bind(query).fetch_all(&self.pool).await?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(self.row_to_entry(row)?);
+        }
+        Ok(entries)
+    }
+
+    // Stores the scope for a conversation; an empty scope removes it
+    pub async fn set_conversation_scope(
+        &self,
+        user_id: &str,
+        conversation_id: &str,
+        scope: &ConversationScope,
+    ) -> Result<()> {
+        if scope.is_empty() {
+            sqlx::query(
+                "DELETE FROM conversation_scopes WHERE user_id = ? AND conversation_id = ?",
+            )
+            .bind(user_id)
+            .bind(conversation_id)
+            .execute(&self.pool)
+            .await?;
+            return Ok(());
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO conversation_scopes (user_id, conversation_id, scope, updated_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (user_id, conversation_id)
+            DO UPDATE SET scope = excluded.scope, updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(conversation_id)
+        .bind(serde_json::to_string(scope)?)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_conversation_scope(
+        &self,
+        user_id: &str,
+        conversation_id: &str,
+    ) -> Result<Option<ConversationScope>> {
+        let scope: Option<String> = sqlx::query_scalar(
+            "SELECT scope FROM conversation_scopes WHERE user_id = ? AND conversation_id = ?",
+        )
+        .bind(user_id)
+        .bind(conversation_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(scope.map(|s| serde_json::from_str(&s)).transpose()?)
+    }
+
+    // Alphabetical by name
+    pub async fn get_personas(&self, user_id: &str) -> Result<Vec<Persona>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM personas WHERE user_id = ? ORDER BY name COLLATE NOCASE, id",
+            PERSONA_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| self.row_to_persona(row))
+            .collect()
+    }
+
+    pub async fn get_persona(&self, user_id: &str, id: &str) -> Result<Option<Persona>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM personas WHERE id = ? AND user_id = ?",
+            PERSONA_COLUMNS
+        ))
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| self.row_to_persona(row)).transpose()
+    }
+
+    pub async fn create_persona(
+        &self,
+        user_id: &str,
+        name: &str,
+        system_prompt: &str,
+    ) -> Result<Persona> {
+        let (name, system_prompt) = validate_persona(name, system_prompt)?;
+        self.ensure_persona_name_free(user_id, name, None).await?;
+
+        let id = Uuid::new_v4().to_string();
+        sqlx::query(
+            "INSERT INTO personas (id, user_id, name, system_prompt, created_at) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&id)
+        .bind(user_id)
+        .bind(name)
+        .bind(system_prompt)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        self.get_persona(user_id, &id)
+            .await?
+            .ok_or_else(|| anyhow!("Persona could not be created"))
+    }
+
+    // Fields left as `None` are kept. Returns `None` if the persona doesn't
+    // exist.
+    pub async fn update_persona(
+        &self,
+        user_id: &str,
+        id: &str,
+        name: Option<&str>,
+        system_prompt: Option<&str>,
+    ) -> Result<Option<Persona>> {
+        let Some(existing) = self.get_persona(user_id, id).await? else {
+            return Ok(None);
+        };
+        let (name, system_prompt) = validate_persona(
+            name.unwrap_or(&existing.name),
+            system_prompt.unwrap_or(&existing.system_prompt),
+        )?;
+        self.ensure_persona_name_free(user_id, name, Some(id))
+            .await?;
+
+        sqlx::query("UPDATE personas SET name = ?, system_prompt = ? WHERE id = ? AND user_id = ?")
+            .bind(name)
+            .bind(system_prompt)
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        self.get_persona(user_id, id).await
+    }
+
+    pub async fn delete_persona(&self, user_id: &str, id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM personas WHERE id = ? AND user_id = ?")
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Names are unique per user, ignoring ASCII case
+    async fn ensure_persona_name_free(
+        &self,
+        user_id: &str,
+        name: &str,
+        except_id: Option<&str>,
+    ) -> Result<()> {
+        let taken = sqlx::query(
+            "SELECT 1 FROM personas WHERE user_id = ? AND name = ? COLLATE NOCASE AND id != ? LIMIT 1",
+        )
+        .bind(user_id)
+        .bind(name)
+        .bind(except_id.unwrap_or_default())
+        .fetch_optional(&self.pool)
+        .await?;
+        if taken.is_some() {
+            return Err(anyhow!("A persona named '{}' already exists", name));
+        }
+        Ok(())
+    }
+
+    // --- Chat persistence ---
+    pub async fn create_chat_message(
+        &self,
+        user_id: &str,
+        conversation_id: &str,
+        content: &str,
+        is_user: bool,
+        sources: Option<&[serde_json::Value]>,
+    ) -> Result<String> {
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+        let sources_json = sources.map(serde_json::to_string).transpose()?;
+
+        sqlx::query(
+            "INSERT INTO chat_messages (id, user_id, conversation_id, content, is_user, sources, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&id)
+        .bind(user_id)
+        .bind(conversation_id)
+        .bind(content)
+        .bind(is_user)
+        .bind(&sources_json)
+        .bind(&now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    pub async fn get_chat_messages(
+        &self,
+        user_id: &str,
+        limit: Option<i32>,
+    ) -> Result<Vec<ChatMessage>> {
+        let limit = limit.unwrap_or(50);
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM chat_messages WHERE user_id = ? ORDER BY created_at DESC, rowid DESC LIMIT ?",
+            CHAT_MESSAGE_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut messages = Vec::new();
+        for row in rows {
+            messages.push(self.row_to_chat_message(row)?);
+        }
+
+        // Reverse to get chronological order
+        messages.reverse();
+        Ok(messages)
+    }
+
+    // Most recent `limit` messages of one conversation, oldest first.
+    // `None` returns the whole conversation.
+    pub async fn get_chat_messages_by_conversation(
+        &self,
+        user_id: &str,
+        conversation_id: &str,
+        limit: Option<i32>,
+    ) -> Result<Vec<ChatMessage>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM chat_messages WHERE user_id = ? AND conversation_id = ? ORDER BY created_at DESC, rowid DESC LIMIT ?",
+            CHAT_MESSAGE_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(conversation_id)
+        .bind(limit.unwrap_or(-1))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut messages = Vec::new();
+        for row in rows {
+            messages.push(self.row_to_chat_message(row)?);
+        }
+
+        messages.reverse();
+        Ok(messages)
+    }
+
+    pub async fn get_chat_message(&self, user_id: &str, id: &str) -> Result<Option<ChatMessage>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM chat_messages WHERE id = ? AND user_id = ?",
+            CHAT_MESSAGE_COLUMNS
+        ))
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => Ok(Some(self.row_to_chat_message(row)?)),
+            None => Ok(None),
+        }
+    }
+
+    // Rewrites a user message (or keeps it, when `new_content` is None), drops
+    // everything after it in its conversation and appends the new assistant
+    // reply, all in one transaction.
+    pub async fn replace_conversation_tail(
+        &self,
+        user_id: &str,
+        message_id: &str,
+        new_content: Option<&str>,
+        answer: &str,
+        sources: &[serde_json::Value],
+    ) -> Result<String> {
+        let mut tx = self.pool.begin().await?;
+
+        let row = sqlx::query(
+            "SELECT rowid, conversation_id, created_at FROM chat_messages WHERE id = ? AND user_id = ?",
+        )
+        .bind(message_id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| anyhow!("Chat message not found"))?;
+        let rowid: i64 = row.try_get("rowid")?;
+        let conversation_id: String = row.try_get("conversation_id")?;
+        let created_at: String = row.try_get("created_at")?;
+        let answer_id = Uuid::new_v4().to_string();
+
+        if let Some(new_content) = new_content {
+            sqlx::query("UPDATE chat_messages SET content = ? WHERE id = ?")
+                .bind(new_content)
+                .bind(message_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        sqlx::query(
+            "DELETE FROM chat_messages WHERE user_id = ? AND conversation_id = ? AND (created_at > ? OR (created_at = ? AND rowid > ?))",
+        )
+        .bind(user_id)
+        .bind(&conversation_id)
+        .bind(&created_at)
+        .bind(&created_at)
+        .bind(rowid)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "INSERT INTO chat_messages (id, user_id, conversation_id, content, is_user, sources, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&answer_id)
+        .bind(user_id)
+        .bind(&conversation_id)
+        .bind(answer)
+        .bind(false)
+        .bind(serde_json::to_string(sources)?)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(answer_id)
+    }
+
+    pub async fn record_chat_metadata(
+        &self,
+        message_id: &str,
+        source_count: usize,
+        generation_ms: Option<u64>,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO chat_metadata (message_id, source_count, generation_ms, created_at) VALUES (?, ?, ?, ?)",
+        )
+        .bind(message_id)
+        .bind(source_count as i64)
+        .bind(generation_ms.map(|ms| ms as i64))
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_chat_analytics(&self, user_id: &str) -> Result<ChatAnalytics> {
+        let row = sqlx::query(
+            r#"
+            SELECT COUNT(*) AS answers,
+                   COALESCE(AVG(cm.source_count), 0.0) AS avg_sources,
+                   COALESCE(SUM(cm.source_count = 0), 0) AS zero_source_answers,
+                   AVG(cm.generation_ms) AS avg_generation_ms
+            FROM chat_metadata cm
+            INNER JOIN chat_messages m ON m.id = cm.message_id
+            WHERE m.user_id = ?
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(ChatAnalytics {
+            answers: row.try_get("answers")?,
+            avg_sources: row.try_get("avg_sources")?,
+            zero_source_answers: row.try_get("zero_source_answers")?,
+            avg_generation_ms: row.try_get("avg_generation_ms")?,
+        })
+    }
+
+    pub async fn set_chat_message_bookmarked(
+        &self,
+        user_id: &str,
+        id: &str,
+        bookmarked: bool,
+    ) -> Result<bool> {
+        let result =
+            sqlx::query("UPDATE chat_messages SET is_bookmarked = ? WHERE id = ? AND user_id = ?")
+                .bind(bookmarked)
+                .bind(id)
+                .bind(user_id)
+                .execute(&self.pool)
+                .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_bookmarked_messages(&self, user_id: &str) -> Result<Vec<ChatMessage>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM chat_messages WHERE user_id = ? AND is_bookmarked = 1 ORDER BY created_at DESC, rowid DESC",
+            CHAT_MESSAGE_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut messages = Vec::new();
+        for row in rows {
+            messages.push(self.row_to_chat_message(row)?);
+        }
+
+        Ok(messages)
+    }
+
+    // Every conversation with at least one message, most recently active first
+    pub async fn list_conversations(&self, user_id: &str) -> Result<Vec<ConversationSummary>> {
+        let rows = sqlx::query(
+            "SELECT conversation_id, COUNT(*) AS message_count, MAX(created_at) AS last_message_at
+             FROM chat_messages WHERE user_id = ?
+             GROUP BY conversation_id
+             ORDER BY last_message_at DESC, conversation_id ASC",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut conversations = Vec::new();
+        for row in rows {
+            conversations.push(ConversationSummary {
+                conversation_id: row.try_get("conversation_id")?,
+                message_count: row.try_get("message_count")?,
+                last_message_at: row.try_get("last_message_at")?,
+            });
+        }
+        Ok(conversations)
+    }
+
+    pub async fn delete_chat_message(&self, user_id: &str, id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM chat_messages WHERE id = ? AND user_id = ?")
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    fn row_to_chat_message(&self, row: SqliteRow) -> Result<ChatMessage> {
+        let sources_str: Option<String> = row.try_get("sources")?;
+        let sources = sources_str.and_then(|s| serde_json::from_str(&s).ok());
+
+        Ok(ChatMessage {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            conversation_id: row.try_get("conversation_id")?,
+            content: row.try_get("content")?,
+            is_user: row.try_get("is_user")?,
+            sources,
+            is_bookmarked: row.try_get("is_bookmarked")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+
+    fn row_to_persona(&self, row: SqliteRow) -> Result<Persona> {
+        Ok(Persona {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            system_prompt: row.try_get("system_prompt")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+
+    fn row_to_field(&self, row: SqliteRow) -> Result<(String, serde_json::Value)> {
+        let key: String = row.try_get("key")?;
+        let value = match FieldType::parse(&row.try_get::<String, _>("value_type")?)? {
+            FieldType::String | FieldType::Date => serde_json::Value::String(row.try_get("value")?),
+            FieldType::Number => serde_json::Value::from(row.try_get::<f64, _>("value")?),
+            FieldType::Bool => serde_json::Value::Bool(row.try_get("value")?),
+        };
+        Ok((key, value))
+    }
+
+    fn row_to_entry(&self, row: SqliteRow) -> Result<JournalEntry> {
+        let tags_str: Option<String> = row.try_get("tags")?;
+        let tags = tags_str.and_then(|s| serde_json::from_str(&s).ok());
+        let body: String = row.try_get("body")?;
+
+        Ok(JournalEntry {
+            id: row.try_get("id")?,
             user_id: row.try_get("user_id")?,
             title: row.try_get("title")?,
-            body: row.try_get("body")?,
+            word_count: word_count(&body) as i64,
+            reading_minutes: reading_minutes(&body),
+            body,
             created_at: DateTime::parse_from_rfc3339(&row.try_get::<String, _>("created_at")?)?
                 .with_timezone(&Utc),
             updated_at: DateTime::parse_from_rfc3339(&row.try_get::<String, _>("updated_at")?)?
                 .with_timezone(&Utc),
+            entry_date: row.try_get("entry_date")?,
             mood: row.try_get("mood")?,
+            mood_intensity: row.try_get("mood_intensity")?,
             tags,
+            visibility: Visibility::parse(&row.try_get::<String, _>("visibility")?)?,
+            pin_order: row.try_get("pin_order")?,
+            deleted_at: row
+                .try_get::<Option<String>, _>("deleted_at")?
+                .map(|at| DateTime::parse_from_rfc3339(&at))
+                .transpose()?
+                .map(|at| at.with_timezone(&Utc)),
+            fields: None,
         })
     }
 }
 
+impl From<JournalEntry> for EntrySummary {
+    fn from(entry: JournalEntry) -> Self {
+        EntrySummary {
+            snippet: snippet(&entry.body, SNIPPET_CHARS),
+            word_count: entry.word_count,
+            id: entry.id,
+            title: entry.title,
+            created_at: entry.created_at,
+            entry_date: entry.entry_date,
+            mood: entry.mood,
+            mood_intensity: entry.mood_intensity,
+            tags: entry.tags,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub id: String,
     pub user_id: String,
+    pub conversation_id: String,
     pub content: String,
     pub is_user: bool,
+    pub sources: Option<Vec<serde_json::Value>>,
+    pub is_bookmarked: bool,
     pub created_at: String,
 }
+
+// Re-orders FTS matches by a blend of normalized bm25 relevance and
+// exponential decays of the time since each entry was written and last
+// edited. bm25 scores are negative, more negative meaning more relevant, so
+// each score is normalized against the best one. Weights adding up to more
+// than 1 are scaled down to share it.
+fn blend_recency(
+    scored: &mut [(JournalEntry, f64)],
+    recency_weight: f64,
+    edit_recency_weight: f64,
+    now: DateTime<Utc>,
+) {
+    let total = recency_weight + edit_recency_weight;
+    let (recency_weight, edit_recency_weight) = if total > 1.0 {
+        (recency_weight / total, edit_recency_weight / total)
+    } else {
+        (recency_weight, edit_recency_weight)
+    };
+    let relevance_weight = 1.0 - recency_weight - edit_recency_weight;
+
+    let best = scored.iter().map(|(_, rank)| *rank).fold(0.0_f64, f64::min);
+    let decay = |at: DateTime<Utc>| {
+        let age_days = (now - at).num_seconds().max(0) as f64 / 86_400.0;
+        (-std::f64::consts::LN_2 * age_days / RECENCY_HALF_LIFE_DAYS).exp()
+    };
+    let blended = |entry: &JournalEntry, rank: f64| {
+        let relevance = if best < 0.0 { rank / best } else { 1.0 };
+        relevance_weight * relevance
+            + recency_weight * decay(entry.created_at)
+            + edit_recency_weight * decay(entry.updated_at)
+    };
+
+    scored.sort_by(|(a, a_rank), (b, b_rank)| blended(b, *b_rank).total_cmp(&blended(a, *a_rank)));
+}
+
+fn date_anomaly(date: DateTime<Utc>, now: DateTime<Utc>) -> Option<DateAnomalyKind> {
+    if date > now + chrono::Duration::hours(MAX_FUTURE_SKEW_HOURS) {
+        Some(DateAnomalyKind::Future)
+    } else if date.year() < EARLIEST_ENTRY_YEAR {
+        Some(DateAnomalyKind::BeforeFloor)
+    } else {
+        None
+    }
+}
+
+fn date_anomaly_message(date: DateTime<Utc>, kind: DateAnomalyKind) -> String {
+    match kind {
+        DateAnomalyKind::Future => format!("Entry date {} is in the future", date.to_rfc3339()),
+        DateAnomalyKind::BeforeFloor => format!(
+            "Entry date {} is before {}",
+            date.to_rfc3339(),
+            EARLIEST_ENTRY_YEAR
+        ),
+    }
+}
+
+fn is_in_memory_url(database_url: &str) -> bool {
+    database_url.contains(":memory:") || database_url.contains("mode=memory")
+}
+
+// Every connection to `sqlite::memory:` opens its own empty database, so the
+// pool is pinned to one connection that is never recycled.
+async fn in_memory_pool(database_url: &str) -> Result<SqlitePool> {
+    let options = SqliteConnectOptions::from_str(database_url)?;
+    let pool = SqlitePoolOptions::new()
+        .min_connections(1)
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(options)
+        .await?;
+    Ok(pool)
+}
+
+// Quotes `text` as a single FTS5 phrase so operators in it are matched literally
+fn fts_phrase(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+// Turns a search box query into an FTS5 query matching entries that contain
+// every word, in any order. Each whitespace-separated token is quoted, so
+// AND/OR/NEAR, `*`, `:` and stray quotes are all taken literally. None when
+// no token has anything the tokenizer would index, e.g. a lone "*".
+fn escape_fts_query(query: &str) -> Option<String> {
+    let tokens: Vec<String> = query
+        .split_whitespace()
+        .filter(|token| token.chars().any(char::is_alphanumeric))
+        .map(fts_phrase)
+        .collect();
+    (!tokens.is_empty()).then(|| tokens.join(" "))
+}
+
+// Distinct lowercase words of three or more characters that aren't stop
+// words, in order of first appearance.
+fn significant_terms(text: &str, max_terms: usize) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        let word = word.to_lowercase();
+        if word.chars().count() < 3 || STOP_WORDS.contains(&word.as_str()) || terms.contains(&word)
+        {
+            continue;
+        }
+        terms.push(word);
+        if terms.len() == max_terms {
+            break;
+        }
+    }
+    terms
+}
+
+fn validate_mood_intensity(intensity: Option<u8>) -> Result<()> {
+    match intensity {
+        Some(value) if !MOOD_INTENSITY_RANGE.contains(&value) => Err(anyhow!(
+            "Mood intensity must be between {} and {}, got {}",
+            MOOD_INTENSITY_RANGE.start(),
+            MOOD_INTENSITY_RANGE.end(),
+            value
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Trimmed name and prompt, both required
+fn validate_persona<'a>(name: &'a str, system_prompt: &'a str) -> Result<(&'a str, &'a str)> {
+    let (name, system_prompt) = (name.trim(), system_prompt.trim());
+    if name.is_empty() {
+        return Err(anyhow!("Persona name must not be empty"));
+    }
+    if system_prompt.is_empty() {
+        return Err(anyhow!("Persona prompt must not be empty"));
+    }
+    Ok((name, system_prompt))
+}
+
+fn parse_entry_date(date: &str) -> Result<NaiveDate> {
+    let parsed = NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .map_err(|_| anyhow!("Invalid entry date '{}', expected YYYY-MM-DD", date))?;
+    if parsed.year() < EARLIEST_ENTRY_YEAR {
+        return Err(anyhow!(
+            "Entry date {} is before {}",
+            parsed,
+            EARLIEST_ENTRY_YEAR
+        ));
+    }
+    Ok(parsed)
+}
+
+fn row_to_entry_version(row: SqliteRow) -> Result<EntryVersion> {
+    let tags: Option<String> = row.try_get("tags")?;
+    let saved_at: String = row.try_get("saved_at")?;
+    Ok(EntryVersion {
+        id: row.try_get("id")?,
+        entry_id: row.try_get("entry_id")?,
+        title: row.try_get("title")?,
+        body: row.try_get("body")?,
+        mood: row.try_get("mood")?,
+        tags: tags.and_then(|t| serde_json::from_str(&t).ok()),
+        saved_at: DateTime::parse_from_rfc3339(&saved_at)?.with_timezone(&Utc),
+    })
+}
+
+// Whitespace-separated tokens with at least one letter or digit, so
+// markdown markers standing alone ("#", "-", "*", "---", "```") aren't
+// counted as words
+pub fn word_count(text: &str) -> usize {
+    text.split_whitespace()
+        .filter(|token| token.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+// Minutes to read `text` at WORDS_PER_MINUTE
+pub fn reading_minutes(text: &str) -> f32 {
+    word_count(text) as f32 / WORDS_PER_MINUTE
+}
+
+// Splits text into chunks of at most `options.size` characters, each
+// starting `options.overlap` characters before the previous one ended. A
+// chunk ends at the last sentence or line break in its second half when
+// there is one, so sentences are only cut when they are very long.
+pub fn chunk_text(text: &str, options: ChunkOptions) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = (start + options.size).min(chars.len());
+        if end < chars.len() {
+            let min_end = start + options.size / 2;
+            if let Some(boundary) = (min_end.max(start + 1)..end)
+                .rev()
+                .find(|&i| is_sentence_break(&chars, i))
+            {
+                end = boundary;
+            }
+        }
+        let chunk: String = chars[start..end].iter().collect();
+        let chunk = chunk.trim();
+        if !chunk.is_empty() {
+            chunks.push(chunk.to_string());
+        }
+        if end == chars.len() {
+            break;
+        }
+        start = end.saturating_sub(options.overlap).max(start + 1);
+    }
+    chunks
+}
+
+// Whether a chunk ending just before `i` ends on a sentence or line break
+fn is_sentence_break(chars: &[char], i: usize) -> bool {
+    let prev = chars[i - 1];
+    prev == '\n' || (matches!(prev, '.' | '!' | '?') && chars[i].is_whitespace())
+}
+
+// First `max_chars` characters of `text` with whitespace collapsed, and an
+// ellipsis when anything was cut off.
+fn snippet(text: &str, max_chars: usize) -> String {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match collapsed.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", collapsed[..end].trim_end()),
+        None => collapsed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh in-memory database with one user
+    async fn test_db() -> (Database, String) {
+        let db = Database::new_in_memory().await.unwrap();
+        let user_id = db.get_or_create_user("test@example.com").await.unwrap();
+        (db, user_id)
+    }
+
+    fn new_entry(title: &str, body: &str) -> CreateEntryRequest {
+        CreateEntryRequest {
+            title: title.to_string(),
+            body: body.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn in_memory_databases_are_separate() {
+        let (first, user_id) = test_db().await;
+        first
+            .create_entry(&user_id, new_entry("Hello", "only in the first"))
+            .await
+            .unwrap();
+
+        let (second, other_user) = test_db().await;
+        assert_eq!(first.count_entries(&user_id).await.unwrap(), 1);
+        assert_eq!(second.count_entries(&other_user).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn tags_only_edit_leaves_updated_at_and_index_alone() {
+        let (db, user_id) = test_db().await;
+        let created = db
+            .create_entry(&user_id, new_entry("Garden", "planted tomatoes"))
+            .await
+            .unwrap();
+        let before = db.get_entry(&created.id).await.unwrap().unwrap();
+        let indexed_before: Vec<i64> =
+            sqlx::query_scalar("SELECT rowid FROM entry_fts WHERE entry_fts MATCH 'tomatoes'")
+                .fetch_all(&db.pool)
+                .await
+                .unwrap();
+
+        let after = db
+            .update_entry(UpdateEntryRequest {
+                id: created.id.clone(),
+                tags: Some(vec!["outdoors".to_string()]),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(after.tags, Some(vec!["outdoors".to_string()]));
+        assert_eq!(after.updated_at, before.updated_at);
+        let indexed_after: Vec<i64> =
+            sqlx::query_scalar("SELECT rowid FROM entry_fts WHERE entry_fts MATCH 'tomatoes'")
+                .fetch_all(&db.pool)
+                .await
+                .unwrap();
+        assert_eq!(indexed_after, indexed_before);
+        assert_eq!(indexed_after.len(), 1);
+    }
+
+    // Moves an entry's timestamps into the past
+    async fn backdate(db: &Database, id: &str, created_days: i64, updated_days: i64) {
+        let now = Utc::now();
+        sqlx::query("UPDATE entries SET created_at = ?, updated_at = ? WHERE id = ?")
+            .bind((now - chrono::Duration::days(created_days)).to_rfc3339())
+            .bind((now - chrono::Duration::days(updated_days)).to_rfc3339())
+            .bind(id)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+    }
+
+    fn search(query: &str) -> SearchRequest {
+        SearchRequest {
+            query: query.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn ids(entries: &[JournalEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn recency_weight_promotes_a_newer_entry() {
+        let (db, user_id) = test_db().await;
+        let old = db
+            .create_entry(&user_id, new_entry("Notes", "garden garden garden garden"))
+            .await
+            .unwrap();
+        backdate(&db, &old.id, 400, 400).await;
+        let new = db
+            .create_entry(
+                &user_id,
+                new_entry("Notes", "a long walk past the garden in the park today"),
+            )
+            .await
+            .unwrap();
+
+        let by_relevance = db.search_entries(&user_id, search("garden")).await.unwrap();
+        assert_eq!(ids(&by_relevance), [old.id.as_str(), new.id.as_str()]);
+
+        let blended = db
+            .search_entries(
+                &user_id,
+                SearchRequest {
+                    recency_weight: Some(0.5),
+                    ..search("garden")
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(ids(&blended), [new.id.as_str(), old.id.as_str()]);
+    }
+
+    #[tokio::test]
+    async fn entries_sharing_a_created_at_keep_a_stable_order() {
+        let (db, user_id) = test_db().await;
+        for title in ["one", "two", "three"] {
+            db.create_entry(&user_id, new_entry(title, "imported"))
+                .await
+                .unwrap();
+        }
+        sqlx::query("UPDATE entries SET created_at = '2026-01-01T09:00:00+00:00'")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        let first = db.get_entries(&user_id, Tz::UTC).await.unwrap();
+        let second = db.get_entries(&user_id, Tz::UTC).await.unwrap();
+        assert_eq!(ids(&first), ids(&second));
+        let mut expected = ids(&first);
+        expected.sort_unstable_by(|a, b| b.cmp(a));
+        assert_eq!(ids(&first), expected);
+
+        let page = db.get_entries_paged(&user_id, Tz::UTC, 2, 1).await.unwrap();
+        assert_eq!(ids(&page), ids(&first)[1..]);
+    }
+
+    #[tokio::test]
+    async fn title_match_ranks_above_body_match() {
+        let (db, user_id) = test_db().await;
+        let in_body = db
+            .create_entry(&user_id, new_entry("Tuesday", "watered the orchard"))
+            .await
+            .unwrap();
+        let in_title = db
+            .create_entry(&user_id, new_entry("Orchard", "watered everything"))
+            .await
+            .unwrap();
+
+        let results = db
+            .search_entries(&user_id, search("orchard"))
+            .await
+            .unwrap();
+        assert_eq!(ids(&results), [in_title.id.as_str(), in_body.id.as_str()]);
+
+        let unweighted = db
+            .search_entries(
+                &user_id,
+                SearchRequest {
+                    title_weight: Some(0.0),
+                    ..search("orchard")
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            ids(&unweighted),
+            [in_body.id.as_str(), in_title.id.as_str()]
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_and_blank_queries() {
+        let (db, user_id) = test_db().await;
+        let entry = db
+            .create_entry(&user_id, new_entry("Monday", "rain all day"))
+            .await
+            .unwrap();
+
+        for query in ["", "   \t\n"] {
+            assert!(db
+                .search_entries(&user_id, search(query))
+                .await
+                .unwrap()
+                .is_empty());
+            let recent = db
+                .search_entries(
+                    &user_id,
+                    SearchRequest {
+                        recent_if_empty: Some(true),
+                        ..search(query)
+                    },
+                )
+                .await
+                .unwrap();
+            assert_eq!(ids(&recent), [entry.id.as_str()]);
+        }
+    }
+
+    #[tokio::test]
+    async fn title_mode_ignores_body_only_terms() {
+        let (db, user_id) = test_db().await;
+        let entry = db
+            .create_entry(&user_id, new_entry("Lighthouse", "fog rolled in early"))
+            .await
+            .unwrap();
+
+        let in_fields = |query: &str, fields| SearchRequest {
+            fields: Some(fields),
+            ..search(query)
+        };
+        let title_only = db
+            .search_entries(&user_id, in_fields("fog", SearchFields::Title))
+            .await
+            .unwrap();
+        assert!(title_only.is_empty());
+        let body_only = db
+            .search_entries(&user_id, in_fields("fog", SearchFields::Body))
+            .await
+            .unwrap();
+        assert_eq!(ids(&body_only), [entry.id.as_str()]);
+        let title_match = db
+            .search_entries(&user_id, in_fields("lighthouse", SearchFields::Title))
+            .await
+            .unwrap();
+        assert_eq!(ids(&title_match), [entry.id.as_str()]);
+    }
+
+    #[tokio::test]
+    async fn creates_with_the_same_client_id_make_one_entry() {
+        let (db, user_id) = test_db().await;
+        let request = CreateEntryRequest {
+            client_id: Some("draft-1".to_string()),
+            ..new_entry("Retry", "sent twice")
+        };
+
+        let first = db.create_entry(&user_id, request.clone()).await.unwrap();
+        let second = db.create_entry(&user_id, request.clone()).await.unwrap();
+        assert_eq!(first.id, second.id);
+        assert_eq!(db.count_entries(&user_id).await.unwrap(), 1);
+
+        // A retry after the entry was trashed neither returns nor recreates it
+        db.delete_entry(&first.id).await.unwrap();
+        assert!(db.create_entry(&user_id, request.clone()).await.is_err());
+        assert_eq!(db.count_entries(&user_id).await.unwrap(), 0);
+
+        // Purging it frees the id again
+        db.purge_entry(&user_id, &first.id).await.unwrap();
+        let recreated = db.create_entry(&user_id, request).await.unwrap();
+        assert_ne!(recreated.id, first.id);
+    }
+
+    #[tokio::test]
+    async fn fts_operators_in_queries_are_matched_literally() {
+        let (db, user_id) = test_db().await;
+        let quote = db
+            .create_entry(&user_id, new_entry("Quote", r#"foo "bar baz"#))
+            .await
+            .unwrap();
+        let ratio = db
+            .create_entry(&user_id, new_entry("Ratio", "mixed a:b evenly"))
+            .await
+            .unwrap();
+        let near = db
+            .create_entry(&user_id, new_entry("Near", "NEAR the river"))
+            .await
+            .unwrap();
+
+        for (query, expected) in [(r#"foo "bar"#, &quote), ("a:b", &ratio), ("NEAR", &near)] {
+            let results = db.search_entries(&user_id, search(query)).await.unwrap();
+            assert_eq!(ids(&results), [expected.id.as_str()], "query {:?}", query);
+        }
+    }
+
+    #[test]
+    fn escaped_queries_quote_every_token() {
+        assert_eq!(
+            escape_fts_query(r#"foo "bar"#).as_deref(),
+            Some(r#""foo" """bar""#)
+        );
+        assert_eq!(escape_fts_query("a:b").as_deref(), Some(r#""a:b""#));
+        assert_eq!(escape_fts_query("NEAR").as_deref(), Some(r#""NEAR""#));
+        assert_eq!(escape_fts_query(" * "), None);
+    }
+
+    #[tokio::test]
+    async fn edit_recency_boost_ranks_a_recent_edit_higher() {
+        let (db, user_id) = test_db().await;
+        let first = db
+            .create_entry(&user_id, new_entry("Ferry", "crossing the bay"))
+            .await
+            .unwrap();
+        let second = db
+            .create_entry(&user_id, new_entry("Ferry", "crossing the bay"))
+            .await
+            .unwrap();
+        let boosted = SearchRequest {
+            edit_recency_weight: Some(0.5),
+            ..search("bay")
+        };
+
+        // Both written long ago and equally relevant; whichever was edited
+        // lately comes first
+        backdate(&db, &first.id, 400, 400).await;
+        backdate(&db, &second.id, 400, 1).await;
+        let results = db.search_entries(&user_id, boosted.clone()).await.unwrap();
+        assert_eq!(ids(&results), [second.id.as_str(), first.id.as_str()]);
+
+        backdate(&db, &first.id, 400, 1).await;
+        backdate(&db, &second.id, 400, 400).await;
+        let results = db.search_entries(&user_id, boosted).await.unwrap();
+        assert_eq!(ids(&results), [first.id.as_str(), second.id.as_str()]);
+    }
+
+    #[tokio::test]
+    async fn lists_order_by_the_local_day() {
+        let (db, user_id) = test_db().await;
+        // Written on the evening of Jan 1 in Los Angeles, already Jan 2 in UTC
+        let evening = db
+            .create_entry(&user_id, new_entry("Evening", "late"))
+            .await
+            .unwrap();
+        // Written days later about Jan 1
+        let dated = db
+            .create_entry(
+                &user_id,
+                CreateEntryRequest {
+                    entry_date: Some("2026-01-01".to_string()),
+                    ..new_entry("Catching up", "about new year's day")
+                },
+            )
+            .await
+            .unwrap();
+        for (id, created_at) in [
+            (&evening.id, "2026-01-02T02:00:00+00:00"),
+            (&dated.id, "2026-01-05T12:00:00+00:00"),
+        ] {
+            sqlx::query("UPDATE entries SET created_at = ? WHERE id = ?")
+                .bind(created_at)
+                .bind(id)
+                .execute(&db.pool)
+                .await
+                .unwrap();
+        }
+
+        let utc = db.get_entries(&user_id, Tz::UTC).await.unwrap();
+        assert_eq!(ids(&utc), [evening.id.as_str(), dated.id.as_str()]);
+
+        let la: Tz = "America/Los_Angeles".parse().unwrap();
+        let local = db.get_entries(&user_id, la).await.unwrap();
+        assert_eq!(ids(&local), [dated.id.as_str(), evening.id.as_str()]);
+        let page = db.get_entries_paged(&user_id, la, 1, 0).await.unwrap();
+        assert_eq!(ids(&page), [dated.id.as_str()]);
+
+        // Cached days are worked out again when the zone changes
+        let page = db.get_entries_paged(&user_id, Tz::UTC, 1, 0).await.unwrap();
+        assert_eq!(ids(&page), [evening.id.as_str()]);
+    }
+
+    #[tokio::test]
+    async fn split_offsets_count_characters() {
+        let (db, user_id) = test_db().await;
+        let entry = db
+            .create_entry(&user_id, new_entry("Café", "café au lait"))
+            .await
+            .unwrap();
+
+        // Character 4 is the space after "café", byte 5
+        let parts = db
+            .split_entry(&user_id, &entry.id, &[4], true)
+            .await
+            .unwrap();
+        let bodies: Vec<&str> = parts.iter().map(|part| part.body.as_str()).collect();
+        assert_eq!(bodies, ["café", "au lait"]);
+        // What's left of the original is four characters, so 4 is past the end
+        assert!(db
+            .split_entry(&user_id, &entry.id, &[4], true)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn merge_saves_the_merged_entry_first() {
+        let (db, user_id) = test_db().await;
+        let into = db
+            .create_entry(&user_id, new_entry("Morning", "coffee"))
+            .await
+            .unwrap();
+        let from = db
+            .create_entry(&user_id, new_entry("Later", "a walk"))
+            .await
+            .unwrap();
+
+        let merged = db
+            .merge_entries(
+                &user_id,
+                &into.id,
+                std::slice::from_ref(&from.id),
+                "\n\n",
+                false,
+            )
+            .await
+            .unwrap();
+        assert_ne!(merged.body, into.body);
+        let history = db.get_entry_history(&user_id, &into.id).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].body, "coffee");
+    }
+
+    #[tokio::test]
+    async fn editing_the_body_drops_its_chunks() {
+        let (db, user_id) = test_db().await;
+        let entry = db
+            .create_entry(&user_id, new_entry("Notes", "first draft"))
+            .await
+            .unwrap();
+        db.create_text_chunks(&entry.id, &user_id, &entry.body, ChunkOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_chunks_for_entry(&user_id, &entry.id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(db
+            .get_chunks_for_entry("someone-else", &entry.id)
+            .await
+            .unwrap()
+            .is_empty());
+
+        db.update_entry(UpdateEntryRequest {
+            id: entry.id.clone(),
+            body: Some("second draft".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert!(db
+            .get_chunks_for_entry(&user_id, &entry.id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_the_owner_can_restore_or_purge_an_entry() {
+        let (db, user_id) = test_db().await;
+        let other = db.create_user("other@example.com").await.unwrap();
+        let entry = db
+            .create_entry(&user_id, new_entry("Mine", "kept"))
+            .await
+            .unwrap();
+        db.set_entry_field(
+            &user_id,
+            &entry.id,
+            "place",
+            &serde_json::json!("home"),
+            FieldType::String,
+        )
+        .await
+        .unwrap();
+        db.delete_entry(&entry.id).await.unwrap();
+
+        assert!(db.restore_entry(&other, &entry.id).await.unwrap().is_none());
+        assert!(!db.purge_entry(&other, &entry.id).await.unwrap());
+        assert!(db.get_entry(&entry.id).await.unwrap().is_some());
+
+        assert!(db.purge_entry(&user_id, &entry.id).await.unwrap());
+        assert!(db.get_entry(&entry.id).await.unwrap().is_none());
+        let fields: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM entry_fields")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(fields, 0);
+    }
+
+    #[tokio::test]
+    async fn hiding_an_entry_drops_its_chunks() {
+        let (db, user_id) = test_db().await;
+        let mut request = new_entry("Doctor", "checkup notes");
+        request.tags = Some(vec!["health".to_string()]);
+        let tagged = db.create_entry(&user_id, request).await.unwrap();
+        let other = db
+            .create_entry(&user_id, new_entry("Plans", "weekend"))
+            .await
+            .unwrap();
+        for entry in [&tagged, &other] {
+            db.create_text_chunks(&entry.id, &user_id, &entry.body, ChunkOptions::default())
+                .await
+                .unwrap();
+        }
+
+        db.update_entry(UpdateEntryRequest {
+            id: other.id.clone(),
+            visibility: Some(Visibility::Private),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        db.set_visibility_by_tag(&user_id, "health", Visibility::AiExcluded)
+            .await
+            .unwrap();
+        for entry in [&tagged, &other] {
+            assert!(db
+                .get_chunks_for_entry(&user_id, &entry.id)
+                .await
+                .unwrap()
+                .is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn auto_vacuum_mode_can_be_switched_on_an_existing_database() {
+        let (db, user_id) = test_db().await;
+        db.create_entry(&user_id, new_entry("Kept", "through the rebuild"))
+            .await
+            .unwrap();
+        assert_eq!(
+            db.diagnostics().await.unwrap().auto_vacuum,
+            AutoVacuum::None
+        );
+
+        db.set_auto_vacuum(AutoVacuum::Incremental).await.unwrap();
+        assert_eq!(
+            db.diagnostics().await.unwrap().auto_vacuum,
+            AutoVacuum::Incremental
+        );
+        assert_eq!(db.count_entries(&user_id).await.unwrap(), 1);
+
+        db.set_auto_vacuum(AutoVacuum::None).await.unwrap();
+        assert_eq!(
+            db.diagnostics().await.unwrap().auto_vacuum,
+            AutoVacuum::None
+        );
+    }
+
+    #[tokio::test]
+    async fn replacing_the_tail_can_keep_the_question() {
+        let (db, user_id) = test_db().await;
+        let question = db
+            .create_chat_message(&user_id, "c", "How did I sleep?", true, None)
+            .await
+            .unwrap();
+        db.create_chat_message(&user_id, "c", "Badly.", false, None)
+            .await
+            .unwrap();
+        db.create_chat_message(&user_id, "c", "Also, on Monday:", false, None)
+            .await
+            .unwrap();
+
+        db.replace_conversation_tail(&user_id, &question, None, "Better lately.", &[])
+            .await
+            .unwrap();
+        let messages = db
+            .get_chat_messages_by_conversation(&user_id, "c", None)
+            .await
+            .unwrap();
+        let contents: Vec<&str> = messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["How did I sleep?", "Better lately."]);
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::db::ChatMessage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+struct ConversationExport<'a> {
+    conversation_id: &'a str,
+    messages: Vec<ExportedMessage<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ExportedMessage<'a> {
+    role: &'static str,
+    content: &'a str,
+    created_at: &'a str,
+    bookmarked: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sources: Option<&'a [Value]>,
+}
+
+fn role(message: &ChatMessage) -> &'static str {
+    if message.is_user {
+        "user"
+    } else {
+        "assistant"
+    }
+}
+
+// Renders a conversation transcript. `messages` must already be in
+// chronological order.
+pub fn render_conversation(
+    conversation_id: &str,
+    messages: &[ChatMessage],
+    format: ExportFormat,
+) -> Result<String> {
+    match format {
+        ExportFormat::Json => {
+            let export = ConversationExport {
+                conversation_id,
+                messages: messages
+                    .iter()
+                    .map(|m| ExportedMessage {
+                        role: role(m),
+                        content: &m.content,
+                        created_at: &m.created_at,
+                        bookmarked: m.is_bookmarked,
+                        sources: m.sources.as_deref().filter(|_| !m.is_user),
+                    })
+                    .collect(),
+            };
+            Ok(serde_json::to_string_pretty(&export)?)
+        }
+        ExportFormat::Markdown => Ok(render_markdown(conversation_id, messages)),
+    }
+}
+
+fn render_markdown(conversation_id: &str, messages: &[ChatMessage]) -> String {
+    let mut out = format!("# Conversation {}\n", conversation_id);
+
+    for message in messages {
+        let mut timestamp = format_timestamp(&message.created_at);
+        if message.is_bookmarked {
+            timestamp.push_str(" · 🔖 Bookmarked");
+        }
+        out.push('\n');
+
+        if message.is_user {
+            // User turns are blockquoted
+            out.push_str(&format!("> **You** · {}\n>\n", timestamp));
+            for line in message.content.lines() {
+                if line.is_empty() {
+                    out.push_str(">\n");
+                } else {
+                    out.push_str(&format!("> {}\n", line));
+                }
+            }
+        } else {
+            out.push_str(&format!("**Assistant** · {}\n\n", timestamp));
+            out.push_str(message.content.trim_end());
+            out.push('\n');
+
+            if let Some(sources) = message.sources.as_ref().filter(|s| !s.is_empty()) {
+                out.push_str("\nSources:\n");
+                for source in sources {
+                    out.push_str(&format!("- {}\n", describe_source(source)));
+                }
+            }
+        }
+    }
+
+    out
+}
+
+fn format_timestamp(created_at: &str) -> String {
+    DateTime::parse_from_rfc3339(created_at)
+        .map(|dt| {
+            dt.with_timezone(&Utc)
+                .format("%Y-%m-%d %H:%M UTC")
+                .to_string()
+        })
+        .unwrap_or_else(|_| created_at.to_string())
+}
+
+// Sources come from the RAG backend as loosely-shaped JSON; prefer a
+// "title (date)" rendering and fall back to the raw value.
+fn describe_source(source: &Value) -> String {
+    match source {
+        Value::String(text) => text.clone(),
+        Value::Object(map) => {
+            let title = map.get("title").and_then(Value::as_str);
+            let date = map.get("date").and_then(Value::as_str);
+            match (title, date) {
+                (Some(title), Some(date)) => format!("{} ({})", title, date),
+                (Some(title), None) => title.to_string(),
+                _ => source.to_string(),
+            }
+        }
+        other => other.to_string(),
+    }
+}
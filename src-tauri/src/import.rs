@@ -0,0 +1,248 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+// Files with other extensions are skipped rather than reported as failures
+const TEXT_EXTENSIONS: &[&str] = &["txt", "md", "markdown"];
+
+// Filename prefixes recognized as the note's date, e.g. "2024-03-09 walk.md",
+// "202403091230 walk.md", or "Jan 2 2024 walk.md", unless the caller supplies
+// its own list. Formats with a time of day come before the bare date they
+// start with, since the first match wins.
+pub const DEFAULT_DATE_FORMATS: &[&str] = &[
+    "%Y-%m-%d",
+    "%Y_%m_%d",
+    "%Y.%m.%d",
+    "%Y%m%d%H%M%S",
+    "%Y%m%d%H%M",
+    "%Y%m%d",
+    "%b %d %Y",
+    "%B %d %Y",
+    "%d %b %Y",
+];
+
+// A leading run of digits is read as a Unix timestamp (seconds) when it
+// falls between 1973 and 2100; other runs, such as "202403091230", are
+// compact dates and go through the formats instead.
+const TIMESTAMP_RANGE: std::ops::RangeInclusive<i64> = 100_000_000..=4_102_444_800;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportFileResult {
+    pub path: String,
+    // Set when the file was imported
+    pub entry_id: Option<String>,
+    // Set when it wasn't
+    pub error: Option<String>,
+    // Set when the file was imported but something about it was guessed
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub failed: usize,
+    // Of the imported files, how many were dated from their filename and how
+    // many fell back to the import time
+    pub dates_parsed: usize,
+    pub dates_defaulted: usize,
+    // Non-text files that were left alone
+    pub skipped: usize,
+    pub files: Vec<ImportFileResult>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportProgress {
+    pub processed: usize,
+    pub total: usize,
+    pub imported: usize,
+    pub failed: usize,
+}
+
+// Lets the user pause a running import from another command. Shared by
+// every clone of the service.
+#[derive(Default)]
+pub struct ImportControl {
+    paused: AtomicBool,
+    resumed: Notify,
+}
+
+impl ImportControl {
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+        self.resumed.notify_waiters();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    // Returns immediately unless paused, in which case it waits for resume
+    pub async fn wait_while_paused(&self) {
+        loop {
+            // Registered before the check so a resume in between isn't missed
+            let resumed = self.resumed.notified();
+            if !self.is_paused() {
+                return;
+            }
+            resumed.await;
+        }
+    }
+}
+
+// Time between inserts for a throttled import
+pub fn throttle_interval(entries_per_second: Option<f64>) -> Result<Option<Duration>> {
+    match entries_per_second {
+        None => Ok(None),
+        Some(rate) if rate.is_finite() && rate > 0.0 => {
+            Ok(Some(Duration::from_secs_f64(1.0 / rate)))
+        }
+        Some(rate) => Err(anyhow!(
+            "Import rate must be a positive number of entries per second, got {}",
+            rate
+        )),
+    }
+}
+
+// A note read from disk, ready to become an entry
+pub struct ParsedNote {
+    pub title: String,
+    pub body: String,
+    // None when no date could be read from the filename
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+// Every file under `dir`, recursively, in path order. Returns the text files
+// and the number of other files found.
+pub fn collect_text_files(dir: &Path) -> Result<(Vec<PathBuf>, usize)> {
+    if !dir.is_dir() {
+        return Err(anyhow!("{} is not a directory", dir.display()));
+    }
+
+    let mut files = Vec::new();
+    let mut skipped = 0;
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for item in fs::read_dir(&current)? {
+            let item = item?;
+            let path = item.path();
+            // The entry's own type, not its target's: following a link to an
+            // ancestor directory would walk in circles, so linked
+            // directories are left out. Linked files are still imported.
+            let file_type = item.file_type()?;
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_symlink() && path.is_dir() {
+                continue;
+            } else if is_text_file(&path) {
+                files.push(path);
+            } else {
+                skipped += 1;
+            }
+        }
+    }
+    files.sort();
+    Ok((files, skipped))
+}
+
+fn is_text_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| TEXT_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+// Reads a note, dating it from its filename: a Unix timestamp as-is, a date
+// in one of `formats` (strftime syntax) in `tz`, at midnight unless the
+// format includes a time
+pub fn read_note(path: &Path, tz: Tz, formats: &[String]) -> Result<ParsedNote> {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    let created_at = parse_date_prefix(stem, tz, formats)?;
+
+    let contents = fs::read(path)?;
+    let contents =
+        String::from_utf8(contents).map_err(|_| anyhow!("File is not valid UTF-8 text"))?;
+    let (title, body) = split_title(&contents);
+    if title.is_empty() {
+        return Err(anyhow!("File is empty"));
+    }
+
+    Ok(ParsedNote {
+        title,
+        body,
+        created_at,
+    })
+}
+
+pub fn default_date_formats() -> Vec<String> {
+    DEFAULT_DATE_FORMATS.iter().map(|f| f.to_string()).collect()
+}
+
+// The date at the start of `text`, or None when nothing there looks like
+// one. Formats are tried in order and the first that parses wins.
+pub fn parse_date_prefix(text: &str, tz: Tz, formats: &[String]) -> Result<Option<DateTime<Utc>>> {
+    let text = text.trim_start();
+    let digits = text.bytes().take_while(u8::is_ascii_digit).count();
+    let timestamp = text[..digits]
+        .parse::<i64>()
+        .ok()
+        .filter(|secs| TIMESTAMP_RANGE.contains(secs))
+        .and_then(|secs| DateTime::from_timestamp(secs, 0));
+    if timestamp.is_some() {
+        return Ok(timestamp);
+    }
+
+    let local = formats.iter().find_map(|format| {
+        NaiveDateTime::parse_and_remainder(text, format)
+            .or_else(|_| {
+                NaiveDate::parse_and_remainder(text, format)
+                    .map(|(date, rest)| (date.and_hms_opt(0, 0, 0).unwrap(), rest))
+            })
+            .ok()
+            .map(|(local, _)| local)
+    });
+    let Some(local) = local else {
+        return Ok(None);
+    };
+    let created_at = tz
+        .from_local_datetime(&local)
+        .earliest()
+        .ok_or_else(|| anyhow!("{} does not exist in {}", local, tz))?;
+    Ok(Some(created_at.with_timezone(&Utc)))
+}
+
+// Rejects formats chrono can't use before any file is read
+pub fn validate_date_formats(formats: &[String]) -> Result<()> {
+    if formats.is_empty() {
+        return Err(anyhow!("At least one date format is required"));
+    }
+    for format in formats {
+        let valid = chrono::format::StrftimeItems::new(format)
+            .all(|item| !matches!(item, chrono::format::Item::Error));
+        if !valid || format.trim().is_empty() {
+            return Err(anyhow!("Invalid date format: {:?}", format));
+        }
+    }
+    Ok(())
+}
+
+// The first non-blank line, minus any markdown heading marker, is the title;
+// everything after it is the body
+fn split_title(contents: &str) -> (String, String) {
+    let contents = contents.trim_start_matches('\u{feff}').trim_start();
+    let (first, rest) = contents.split_once('\n').unwrap_or((contents, ""));
+    let title = first.trim().trim_start_matches('#').trim();
+    (title.to_string(), rest.trim().to_string())
+}
@@ -0,0 +1,270 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::db::{word_count, EntrySummary, JournalEntry};
+
+// Period that entries are grouped into for trends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrendBucket {
+    Day,
+    Week,
+    Month,
+}
+
+impl TrendBucket {
+    // First day of the period containing `date`; weeks start on Monday
+    fn start_of(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            TrendBucket::Day => date,
+            TrendBucket::Week => date - Days::new(date.weekday().num_days_from_monday() as u64),
+            TrendBucket::Month => date.with_day(1).unwrap(),
+        }
+    }
+
+    fn next(self, start: NaiveDate) -> NaiveDate {
+        match self {
+            TrendBucket::Day => start + Days::new(1),
+            TrendBucket::Week => start + Days::new(7),
+            TrendBucket::Month => start + Months::new(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LengthTrendPoint {
+    // First day of the period, YYYY-MM-DD
+    pub period: String,
+    pub entry_count: usize,
+    // `None` for periods without entries, so charts show a gap rather than 0
+    pub average_words: Option<f64>,
+}
+
+// Average entry length per period, from the first period with an entry to
+// the last. Periods follow the calendar in `tz`.
+pub fn length_trend(
+    entries: &[JournalEntry],
+    bucket: TrendBucket,
+    tz: Tz,
+) -> Vec<LengthTrendPoint> {
+    let mut lengths: Vec<(NaiveDate, usize)> = entries
+        .iter()
+        .map(|entry| {
+            let date = entry_day(entry, tz);
+            (bucket.start_of(date), word_count(&entry.body))
+        })
+        .collect();
+    lengths.sort_by_key(|(period, _)| *period);
+
+    let (Some(first), Some(last)) = (lengths.first(), lengths.last()) else {
+        return Vec::new();
+    };
+    let (mut period, last_period) = (first.0, last.0);
+
+    let mut points = Vec::new();
+    let mut remaining = lengths.as_slice();
+    while period <= last_period {
+        let count = remaining.iter().take_while(|(p, _)| *p == period).count();
+        let (in_period, rest) = remaining.split_at(count);
+        let total: usize = in_period.iter().map(|(_, words)| words).sum();
+
+        points.push(LengthTrendPoint {
+            period: period.format("%Y-%m-%d").to_string(),
+            entry_count: count,
+            average_words: (count > 0).then(|| total as f64 / count as f64),
+        });
+
+        remaining = rest;
+        period = bucket.next(period);
+    }
+    points
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaySummary {
+    // YYYY-MM-DD in the user's time zone
+    pub date: String,
+    // Oldest first
+    pub entries: Vec<EntrySummary>,
+    pub total_words: usize,
+    pub dominant_mood: Option<String>,
+    // One-paragraph recap from the assistant, when asked for
+    pub recap: Option<String>,
+    // Shown in place of the card's contents on days without entries
+    pub empty_message: Option<String>,
+}
+
+// Entries about `date` in `tz`, oldest first
+pub fn entries_on(entries: Vec<JournalEntry>, date: NaiveDate, tz: Tz) -> Vec<JournalEntry> {
+    let mut on_day: Vec<JournalEntry> = entries
+        .into_iter()
+        .filter(|entry| entry_day(entry, tz) == date)
+        .collect();
+    on_day.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id)));
+    on_day
+}
+
+// The mood felt most across the entries, with each entry counting for its
+// intensity (3 when unset). Ties go to the alphabetically first label.
+pub fn dominant_mood(entries: &[JournalEntry]) -> Option<String> {
+    let mut weights: BTreeMap<&str, u32> = BTreeMap::new();
+    for entry in entries {
+        let Some(mood) = entry
+            .mood
+            .as_deref()
+            .map(str::trim)
+            .filter(|m| !m.is_empty())
+        else {
+            continue;
+        };
+        *weights.entry(mood).or_default() += u32::from(entry.mood_intensity.unwrap_or(3));
+    }
+    weights
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, weight)| *weight)
+        .map(|(mood, _)| mood.to_string())
+}
+
+// Mood bucket in MoodStats for entries saved without one
+pub const MOOD_UNSPECIFIED: &str = "unspecified";
+
+// Entries per mood, overall and per day, for a mood trend chart. Entries
+// without a mood are counted under MOOD_UNSPECIFIED.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MoodStats {
+    pub total: i64,
+    pub moods: BTreeMap<String, i64>,
+    // Keyed by the day each entry is about
+    pub daily: BTreeMap<NaiveDate, BTreeMap<String, i64>>,
+}
+
+// Moods are counted as entered, trimmed; days follow the calendar in `tz`
+pub fn mood_stats(entries: &[JournalEntry], tz: Tz) -> MoodStats {
+    let mut stats = MoodStats::default();
+    for entry in entries {
+        let mood = entry
+            .mood
+            .as_deref()
+            .map(str::trim)
+            .filter(|mood| !mood.is_empty())
+            .unwrap_or(MOOD_UNSPECIFIED);
+        stats.total += 1;
+        *stats.moods.entry(mood.to_string()).or_default() += 1;
+        *stats
+            .daily
+            .entry(entry_day(entry, tz))
+            .or_default()
+            .entry(mood.to_string())
+            .or_default() += 1;
+    }
+    stats
+}
+
+// The day an entry is about: its entry date when set, otherwise the day it
+// was written in `tz`
+pub fn entry_day(entry: &JournalEntry, tz: Tz) -> NaiveDate {
+    entry
+        .entry_date
+        .unwrap_or_else(|| local_date(entry.created_at, tz))
+}
+
+// Newest first by the day each entry is about in `tz`, then by created_at;
+// `id` breaks ties between entries sharing a created_at (e.g. bulk imports)
+// so the order is stable across calls
+pub fn sort_newest_first(entries: &mut [JournalEntry], tz: Tz) {
+    entries.sort_by_cached_key(|entry| {
+        Reverse((entry_day(entry, tz), entry.created_at, entry.id.clone()))
+    });
+}
+
+fn local_date(date: DateTime<Utc>, tz: Tz) -> NaiveDate {
+    date.with_timezone(&tz).date_naive()
+}
+
+// A labeled query for evaluate_retrieval: the entries a good search for
+// `query` should turn up
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetrievalQuery {
+    pub query: String,
+    pub expected_entry_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryEvaluation {
+    pub query: String,
+    // Share of the expected entries found in the top k
+    pub recall: f64,
+    // 1 / rank of the first expected entry, 0 when none was found
+    pub reciprocal_rank: f64,
+    // Ids returned, best first
+    pub retrieved: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetrievalEvaluation {
+    pub k: usize,
+    // Averages over the queries that had expected entries
+    pub recall_at_k: f64,
+    pub mrr: f64,
+    pub queries: Vec<QueryEvaluation>,
+}
+
+// Scores one query's results against its expected ids. Queries without
+// expected ids score zero and are left out of the averages.
+pub fn score_retrieval(query: &RetrievalQuery, retrieved: Vec<String>) -> QueryEvaluation {
+    let expected: HashSet<&str> = query
+        .expected_entry_ids
+        .iter()
+        .map(String::as_str)
+        .collect();
+    let found = retrieved
+        .iter()
+        .filter(|id| expected.contains(id.as_str()))
+        .count();
+    let recall = if expected.is_empty() {
+        0.0
+    } else {
+        found as f64 / expected.len() as f64
+    };
+    let reciprocal_rank = retrieved
+        .iter()
+        .position(|id| expected.contains(id.as_str()))
+        .map_or(0.0, |i| 1.0 / (i + 1) as f64);
+    QueryEvaluation {
+        query: query.query.clone(),
+        recall,
+        reciprocal_rank,
+        retrieved,
+    }
+}
+
+pub fn summarize_retrieval(
+    k: usize,
+    queries: &[RetrievalQuery],
+    scored: Vec<QueryEvaluation>,
+) -> RetrievalEvaluation {
+    let labeled: Vec<&QueryEvaluation> = queries
+        .iter()
+        .zip(&scored)
+        .filter(|(query, _)| !query.expected_entry_ids.is_empty())
+        .map(|(_, score)| score)
+        .collect();
+    let mean = |value: fn(&QueryEvaluation) -> f64| {
+        if labeled.is_empty() {
+            0.0
+        } else {
+            labeled.iter().map(|s| value(s)).sum::<f64>() / labeled.len() as f64
+        }
+    };
+    RetrievalEvaluation {
+        k,
+        recall_at_k: mean(|s| s.recall),
+        mrr: mean(|s| s.reciprocal_rank),
+        queries: scored,
+    }
+}
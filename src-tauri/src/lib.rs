@@ -1,155 +1,700 @@
+mod dates;
 mod db;
+mod export;
+mod import;
+mod insights;
+mod python;
+mod queue;
+mod service;
+mod transform;
+mod webhook;
 
-use db::{
-    ChatMessage, CreateEntryRequest, Database, JournalEntry, SearchRequest, UpdateEntryRequest,
+pub use dates::DateDisplay;
+pub use db::{
+    AutoVacuum, ChatAnalytics, ChatMessage, ChunkOptions, ConversationScope, ConversationSummary,
+    CreateEntryRequest, Database, DatabaseDiagnostics, DateAnomaly, EntryPage, EntrySummary,
+    EntryVersion, FieldOp, FieldType, JournalEntry, MergeEntriesRequest, OrphanRow, Persona,
+    ReferencedEntry, ResetCounts, SearchCapabilities, SearchFields, SearchPage, SearchRequest,
+    SplitEntryRequest, TagCount, TagPair, TextChunk, TopicCheck, UpdateEntryRequest, Visibility,
+    WalCheckpoint,
 };
+pub use export::ExportFormat;
+pub use import::{ImportFileResult, ImportProgress, ImportReport};
+pub use insights::{
+    DaySummary, LengthTrendPoint, MoodStats, QueryEvaluation, RetrievalEvaluation, RetrievalQuery,
+    TrendBucket,
+};
+pub use python::{
+    AnswerCleanup, AnswerStyle, ForwardedContext, GenerationParams, PythonChatRequest,
+    PythonChatResponse, PythonPayloadMapping,
+};
+pub use queue::GenerationQueueStatus;
+pub use service::{Service, ServiceConfig};
+pub use transform::{BodyTransform, TransformSettings};
+pub use webhook::WebhookConfig;
 
 use anyhow::Result;
-use reqwest;
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
-use tauri::{AppHandle, Manager, State};
-
-// Python RAG Service integration
-#[derive(Debug, Serialize, Deserialize)]
-pub struct PythonChatRequest {
-    pub user_id: String,
-    pub message: String,
-    pub conversation_id: Option<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct PythonChatResponse {
-    pub answer: String,
-    pub sources: Vec<serde_json::Value>,
-    pub conversation_id: String,
-}
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
 
 // Global state for the application
 pub struct AppState {
-    db: Mutex<Option<Database>>,
-    user_id: Mutex<Option<String>>,
+    service: Mutex<Option<Service>>,
+    python_payload: Mutex<PythonPayloadMapping>,
+    date_display: Mutex<DateDisplay>,
+    webhook: Mutex<WebhookConfig>,
+    answer_cleanup: Mutex<AnswerCleanup>,
+    max_pins: Mutex<Option<u32>>,
+    incremental_vacuum: Mutex<bool>,
+    transforms: Mutex<TransformSettings>,
+    python_service_url: Mutex<String>,
 }
 
 impl AppState {
     fn new() -> Self {
         AppState {
-            db: Mutex::new(None),
-            user_id: Mutex::new(None),
+            service: Mutex::new(None),
+            python_payload: Mutex::new(PythonPayloadMapping::default()),
+            date_display: Mutex::new(DateDisplay::default()),
+            webhook: Mutex::new(WebhookConfig::default()),
+            answer_cleanup: Mutex::new(AnswerCleanup::default()),
+            max_pins: Mutex::new(None),
+            incremental_vacuum: Mutex::new(false),
+            transforms: Mutex::new(TransformSettings::default()),
+            python_service_url: Mutex::new(python::DEFAULT_PYTHON_SERVICE_URL.to_string()),
+        }
+    }
+
+    fn config(&self) -> ServiceConfig {
+        ServiceConfig {
+            python_payload: self.python_payload.lock().unwrap().clone(),
+            date_display: self.date_display.lock().unwrap().clone(),
+            webhook: self.webhook.lock().unwrap().clone(),
+            answer_cleanup: self.answer_cleanup.lock().unwrap().clone(),
+            max_pins: *self.max_pins.lock().unwrap(),
+            incremental_vacuum: *self.incremental_vacuum.lock().unwrap(),
+            transforms: self.transforms.lock().unwrap().clone(),
+            python_service_url: Some(self.python_service_url.lock().unwrap().clone()),
         }
     }
+
+    // Snapshot of the service with the current configuration applied, so
+    // commands never hold a lock across an await.
+    fn service(&self) -> Result<Service, String> {
+        let service = self
+            .service
+            .lock()
+            .unwrap()
+            .as_ref()
+            .cloned()
+            .ok_or("Database not initialized")?;
+        Ok(service.with_config(self.config()))
+    }
 }
 
-#[tauri::command]
-async fn initialize_database(state: State<'_, AppState>, app: AppHandle) -> Result<String, String> {
+// Each profile is an independent database file, `<name>.db`, in the app
+// data directory. The default profile is the original journal.db.
+const DEFAULT_PROFILE: &str = "journal";
+const PROFILE_EXTENSION: &str = "db";
+
+fn profiles_dir(app: &AppHandle) -> Result<PathBuf, String> {
     let app_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
     std::fs::create_dir_all(&app_dir).map_err(|e| e.to_string())?;
+    Ok(app_dir)
+}
+
+// Overrides where the default profile's database lives, e.g. to keep it on
+// an encrypted volume or point tests at a temporary file
+const DATABASE_PATH_ENV: &str = "DATABASE_PATH";
 
-    let db_path = app_dir.join("journal.db");
+const INCREMENTAL_VACUUM_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+fn profile_db_path(app: &AppHandle, name: &str) -> Result<PathBuf, String> {
+    let valid_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_name {
+        return Err(format!(
+            "Invalid profile name '{}': use letters, digits, '-' and '_'",
+            name
+        ));
+    }
+    Ok(profiles_dir(app)?.join(format!("{}.{}", name, PROFILE_EXTENSION)))
+}
+
+// Checks that a database file can be created next to `db_path` by writing
+// and removing a probe file, which catches read-only mounts and ACLs that
+// permission bits alone don't show.
+fn ensure_writable_parent(db_path: &Path) -> Result<(), String> {
+    let parent = match db_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    std::fs::create_dir_all(parent)
+        .map_err(|e| format!("Cannot create {}: {}", parent.display(), e))?;
+
+    let probe = parent.join(".journal-write-test");
+    std::fs::write(&probe, b"")
+        .and_then(|_| std::fs::remove_file(&probe))
+        .map_err(|e| format!("Directory {} is not writable: {}", parent.display(), e))
+}
+
+// Opens the database at `db_path` and makes it the active one, closing the
+// previously active database. Returns the user id.
+async fn open_database(state: &AppState, db_path: &Path) -> Result<String, String> {
+    log::info!("Opening database at {}", db_path.display());
     let db_url = format!("sqlite:{}", db_path.to_string_lossy());
 
-    let database = Database::new(&db_url).await.map_err(|e| e.to_string())?;
+    let service = Service::connect(&db_url, state.config())
+        .await
+        .map_err(|e| e.to_string())?;
+    let user_id = service.user_id().to_string();
 
-    // Create default user if none exists
-    let user_id = database
-        .get_or_create_user("default@journal.app")
+    // The file remembers its auto_vacuum mode, so the periodic vacuum
+    // follows whatever the opened database was set to
+    let diagnostics = service
+        .database_diagnostics()
         .await
         .map_err(|e| e.to_string())?;
-    log::info!("Default user ID: {}", user_id);
+    *state.incremental_vacuum.lock().unwrap() = diagnostics.auto_vacuum == AutoVacuum::Incremental;
 
-    *state.db.lock().unwrap() = Some(database);
-    *state.user_id.lock().unwrap() = Some(user_id.clone());
+    let previous = state.service.lock().unwrap().replace(service);
+    if let Some(previous) = previous {
+        previous.db().close().await;
+    }
 
     Ok(user_id)
 }
 
+#[tauri::command]
+async fn initialize_database(state: State<'_, AppState>, app: AppHandle) -> Result<String, String> {
+    let db_path = match std::env::var_os(DATABASE_PATH_ENV).filter(|p| !p.is_empty()) {
+        Some(path) => {
+            let path = PathBuf::from(path);
+            ensure_writable_parent(&path)?;
+            path
+        }
+        None => profile_db_path(&app, DEFAULT_PROFILE)?,
+    };
+    open_database(&state, &db_path).await
+}
+
+#[tauri::command]
+async fn switch_profile(
+    state: State<'_, AppState>,
+    app: AppHandle,
+    name: String,
+) -> Result<String, String> {
+    let db_path = profile_db_path(&app, &name)?;
+    open_database(&state, &db_path).await
+}
+
+#[tauri::command]
+async fn list_profiles(app: AppHandle) -> Result<Vec<String>, String> {
+    let mut profiles = Vec::new();
+    for entry in std::fs::read_dir(profiles_dir(&app)?).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(PROFILE_EXTENSION) {
+            continue;
+        }
+        if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+            profiles.push(name.to_string());
+        }
+    }
+    profiles.sort();
+    Ok(profiles)
+}
+
+// `user_id`, when given, must be the current user; the frontend should
+// switch to the returned `user_id` afterwards
+#[tauri::command]
+async fn reset_user_data(
+    state: State<'_, AppState>,
+    user_id: Option<String>,
+    confirm_token: String,
+    delete_user: Option<bool>,
+) -> Result<ResetCounts, String> {
+    let mut service = state.service()?;
+    if user_id.is_some_and(|id| id != service.user_id()) {
+        return Err("Only the current user's data can be reset".to_string());
+    }
+    let counts = service
+        .reset_user_data(&confirm_token, delete_user.unwrap_or(false))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if counts.user_deleted {
+        *state.service.lock().unwrap() = Some(service);
+    }
+    Ok(counts)
+}
+
+// Flushes the write-ahead log into journal.db, e.g. before copying the file
+#[tauri::command]
+async fn checkpoint_wal(state: State<'_, AppState>) -> Result<WalCheckpoint, String> {
+    let service = state.service()?;
+    service.checkpoint_wal().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_database_diagnostics(
+    state: State<'_, AppState>,
+) -> Result<DatabaseDiagnostics, String> {
+    let service = state.service()?;
+    service
+        .database_diagnostics()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Debugging aid: lists orphaned rows without touching them. Use
+// reset_user_data to clean up.
+#[tauri::command]
+async fn audit_ownership(
+    state: State<'_, AppState>,
+) -> Result<BTreeMap<String, Vec<OrphanRow>>, String> {
+    let service = state.service()?;
+    service.audit_ownership().await.map_err(|e| e.to_string())
+}
+
+// Repairs a search index the audit reports as out of sync
+#[tauri::command]
+async fn rebuild_search_index(state: State<'_, AppState>) -> Result<(), String> {
+    let service = state.service()?;
+    service
+        .rebuild_search_index()
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn create_entry(
     state: State<'_, AppState>,
     request: CreateEntryRequest,
 ) -> Result<JournalEntry, String> {
-    let db = {
-        let db_guard = state.db.lock().unwrap();
-        db_guard.as_ref().ok_or("Database not initialized")?.clone()
-    };
-
-    let user_id = state
-        .user_id
-        .lock()
-        .unwrap()
-        .as_ref()
-        .cloned()
-        .ok_or("User not initialized")?;
+    let service = state.service()?;
+    service
+        .create_entry(request)
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    let entry = db
-        .create_entry(&user_id, request)
+// Progress is reported to the frontend as `import-progress` events
+#[tauri::command]
+async fn import_directory(
+    state: State<'_, AppState>,
+    app: AppHandle,
+    path: String,
+    entries_per_second: Option<f64>,
+    date_formats: Option<Vec<String>>,
+) -> Result<ImportReport, String> {
+    let service = state.service()?;
+    service
+        .import_directory(
+            Path::new(&path),
+            entries_per_second,
+            date_formats,
+            |progress| {
+                if let Err(e) = app.emit("import-progress", progress) {
+                    log::warn!("Failed to emit import progress: {}", e);
+                }
+            },
+        )
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| e.to_string())
+}
 
-    // TODO: Index the entry for RAG when we implement thread-safe LLM handling
+#[tauri::command]
+async fn pause_import(state: State<'_, AppState>) -> Result<(), String> {
+    state.service()?.pause_import();
+    Ok(())
+}
 
-    Ok(entry)
+#[tauri::command]
+async fn resume_import(state: State<'_, AppState>) -> Result<(), String> {
+    state.service()?.resume_import();
+    Ok(())
 }
 
 #[tauri::command]
 async fn get_entries(state: State<'_, AppState>) -> Result<Vec<JournalEntry>, String> {
-    let db = {
-        let db_guard = state.db.lock().unwrap();
-        db_guard.as_ref().ok_or("Database not initialized")?.clone()
-    };
+    let service = state.service()?;
+    service.get_entries().await.map_err(|e| e.to_string())
+}
 
-    let user_id = state
-        .user_id
-        .lock()
-        .unwrap()
-        .as_ref()
-        .cloned()
-        .ok_or("User not initialized")?;
+// The list view loads entries a page at a time instead of all at once
+#[tauri::command]
+async fn get_entries_page(
+    state: State<'_, AppState>,
+    limit: i64,
+    offset: i64,
+) -> Result<EntryPage, String> {
+    let service = state.service()?;
+    service
+        .get_entries_page(limit, offset)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_entries_by_word_count(
+    state: State<'_, AppState>,
+    min_words: Option<i64>,
+    max_words: Option<i64>,
+) -> Result<Vec<JournalEntry>, String> {
+    let service = state.service()?;
+    service
+        .get_entries_by_word_count(min_words, max_words)
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    let entries = db.get_entries(&user_id).await.map_err(|e| e.to_string())?;
-    Ok(entries)
+#[tauri::command]
+async fn get_entry_summaries(state: State<'_, AppState>) -> Result<Vec<EntrySummary>, String> {
+    let service = state.service()?;
+    service
+        .get_entry_summaries()
+        .await
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 async fn get_entry(state: State<'_, AppState>, id: String) -> Result<Option<JournalEntry>, String> {
-    let db = {
-        let db_guard = state.db.lock().unwrap();
-        db_guard.as_ref().ok_or("Database not initialized")?.clone()
-    };
+    let service = state.service()?;
+    service.get_entry(&id).await.map_err(|e| e.to_string())
+}
 
-    let entry = db.get_entry(&id).await.map_err(|e| e.to_string())?;
-    Ok(entry)
+// Earlier versions of an entry, newest first
+#[tauri::command]
+async fn get_entry_history(
+    state: State<'_, AppState>,
+    entry_id: String,
+) -> Result<Vec<EntryVersion>, String> {
+    let service = state.service()?;
+    service
+        .get_entry_history(&entry_id)
+        .await
+        .map_err(|e| e.to_string())
 }
 
+// Applies a version from get_entry_history; the current text becomes a
+// version of its own
+#[tauri::command]
+async fn restore_entry_version(
+    state: State<'_, AppState>,
+    version_id: String,
+) -> Result<Option<JournalEntry>, String> {
+    let service = state.service()?;
+    service
+        .restore_entry_version(&version_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Splits an entry into overlapping chunks, replacing any stored before.
+// Options default to 500 characters with 50 of overlap.
+#[tauri::command]
+async fn chunk_entry(
+    state: State<'_, AppState>,
+    id: String,
+    options: Option<ChunkOptions>,
+) -> Result<Option<Vec<TextChunk>>, String> {
+    let service = state.service()?;
+    service
+        .chunk_entry(&id, options.unwrap_or_default())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_entry_chunks(
+    state: State<'_, AppState>,
+    id: String,
+) -> Result<Vec<TextChunk>, String> {
+    let service = state.service()?;
+    service
+        .get_entry_chunks(&id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn entry_exists(state: State<'_, AppState>, id: String) -> Result<bool, String> {
+    let service = state.service()?;
+    service.entry_exists(&id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn title_exists(state: State<'_, AppState>, title: String) -> Result<bool, String> {
+    let service = state.service()?;
+    service
+        .title_exists(&title)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_entries_by_ids(
+    state: State<'_, AppState>,
+    ids: Vec<String>,
+) -> Result<Vec<JournalEntry>, String> {
+    let service = state.service()?;
+    service
+        .get_entries_by_ids(&ids)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_entry_field(
+    state: State<'_, AppState>,
+    entry_id: String,
+    key: String,
+    value: serde_json::Value,
+    value_type: FieldType,
+) -> Result<bool, String> {
+    let service = state.service()?;
+    service
+        .set_entry_field(&entry_id, &key, &value, value_type)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_entry_fields(
+    state: State<'_, AppState>,
+    entry_id: String,
+) -> Result<BTreeMap<String, serde_json::Value>, String> {
+    let service = state.service()?;
+    service
+        .get_entry_fields(&entry_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_entries_where_field(
+    state: State<'_, AppState>,
+    key: String,
+    op: FieldOp,
+    value: serde_json::Value,
+    value_type: FieldType,
+) -> Result<Vec<JournalEntry>, String> {
+    let service = state.service()?;
+    service
+        .get_entries_where_field(&key, op, &value, value_type)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Emits `entry-tagged` / `entry-mood-changed` when an edit changed them,
+// so widgets showing one value can refresh without refetching the entry
 #[tauri::command]
 async fn update_entry(
     state: State<'_, AppState>,
+    app: AppHandle,
     request: UpdateEntryRequest,
 ) -> Result<Option<JournalEntry>, String> {
-    let db = {
-        let db_guard = state.db.lock().unwrap();
-        db_guard.as_ref().ok_or("Database not initialized")?.clone()
-    };
+    let service = state.service()?;
+    let before = service
+        .db()
+        .get_entry(&request.id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let entry = service
+        .update_entry(request)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if let (Some(before), Some(after)) = (&before, &entry) {
+        if before.tags != after.tags {
+            let payload = serde_json::json!({ "id": after.id, "tags": after.tags });
+            emit_entry_event(&app, "entry-tagged", payload);
+        }
+        if before.mood != after.mood || before.mood_intensity != after.mood_intensity {
+            let payload = serde_json::json!({
+                "id": after.id,
+                "mood": after.mood,
+                "mood_intensity": after.mood_intensity,
+            });
+            emit_entry_event(&app, "entry-mood-changed", payload);
+        }
+    }
+    Ok(entry)
+}
+
+fn emit_entry_event(app: &AppHandle, event: &str, payload: serde_json::Value) {
+    if let Err(e) = app.emit(event, payload) {
+        log::warn!("Failed to emit {}: {}", event, e);
+    }
+}
 
-    let entry = db.update_entry(request).await.map_err(|e| e.to_string())?;
+// Bulk privacy toggle, e.g. keep every "health" entry out of AI features.
+// Returns the number of entries changed.
+#[tauri::command]
+async fn set_visibility_by_tag(
+    state: State<'_, AppState>,
+    tag: String,
+    visibility: Visibility,
+) -> Result<usize, String> {
+    let service = state.service()?;
+    service
+        .set_visibility_by_tag(&tag, visibility)
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    // TODO: Re-index the entry for RAG when we implement thread-safe LLM handling
+#[tauri::command]
+async fn find_anomalous_dates(state: State<'_, AppState>) -> Result<Vec<DateAnomaly>, String> {
+    let service = state.service()?;
+    service
+        .find_anomalous_dates()
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    Ok(entry)
+#[tauri::command]
+async fn fix_entry_date(
+    state: State<'_, AppState>,
+    id: String,
+    created_at: DateTime<Utc>,
+) -> Result<Option<JournalEntry>, String> {
+    let service = state.service()?;
+    service
+        .fix_entry_date(&id, created_at)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn append_to_entry(
+    state: State<'_, AppState>,
+    id: String,
+    text: String,
+    separator: Option<String>,
+) -> Result<Option<JournalEntry>, String> {
+    let service = state.service()?;
+    service
+        .append_to_entry(&id, &text, separator.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn merge_entries(
+    state: State<'_, AppState>,
+    request: MergeEntriesRequest,
+) -> Result<JournalEntry, String> {
+    let service = state.service()?;
+    service
+        .merge_entries(request)
+        .await
+        .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+async fn split_entry(
+    state: State<'_, AppState>,
+    request: SplitEntryRequest,
+) -> Result<Vec<JournalEntry>, String> {
+    let service = state.service()?;
+    service
+        .split_entry(request)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Moves the entry to the trash; see restore_entry and purge_entry
 #[tauri::command]
 async fn delete_entry(state: State<'_, AppState>, id: String) -> Result<bool, String> {
-    let db = {
-        let db_guard = state.db.lock().unwrap();
-        db_guard.as_ref().ok_or("Database not initialized")?.clone()
-    };
+    let service = state.service()?;
+    service.delete_entry(&id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn restore_entry(
+    state: State<'_, AppState>,
+    id: String,
+) -> Result<Option<JournalEntry>, String> {
+    let service = state.service()?;
+    service.restore_entry(&id).await.map_err(|e| e.to_string())
+}
 
-    let deleted = db.delete_entry(&id).await.map_err(|e| e.to_string())?;
+#[tauri::command]
+async fn get_trashed_entries(state: State<'_, AppState>) -> Result<Vec<JournalEntry>, String> {
+    let service = state.service()?;
+    service
+        .get_trashed_entries()
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    // TODO: Remove from RAG index when we implement thread-safe LLM handling
+// Deletes the entry for good, whether or not it is in the trash
+#[tauri::command]
+async fn purge_entry(state: State<'_, AppState>, id: String) -> Result<bool, String> {
+    let service = state.service()?;
+    service.purge_entry(&id).await.map_err(|e| e.to_string())
+}
 
-    Ok(deleted)
+#[tauri::command]
+async fn pin_entry(
+    state: State<'_, AppState>,
+    app: AppHandle,
+    id: String,
+) -> Result<Option<JournalEntry>, String> {
+    let service = state.service()?;
+    let entry = service.pin_entry(&id).await.map_err(|e| e.to_string())?;
+    if let Some(entry) = &entry {
+        let payload =
+            serde_json::json!({ "id": entry.id, "pinned": true, "pin_order": entry.pin_order });
+        emit_entry_event(&app, "entry-pinned", payload);
+    }
+    Ok(entry)
+}
+
+#[tauri::command]
+async fn unpin_entry(
+    state: State<'_, AppState>,
+    app: AppHandle,
+    id: String,
+) -> Result<bool, String> {
+    let service = state.service()?;
+    let unpinned = service.unpin_entry(&id).await.map_err(|e| e.to_string())?;
+    if unpinned {
+        let payload = serde_json::json!({ "id": id, "pinned": false, "pin_order": null });
+        emit_entry_event(&app, "entry-pinned", payload);
+    }
+    Ok(unpinned)
+}
+
+#[tauri::command]
+async fn get_pinned_entries(state: State<'_, AppState>) -> Result<Vec<JournalEntry>, String> {
+    let service = state.service()?;
+    service
+        .get_pinned_entries()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn reorder_pins(
+    state: State<'_, AppState>,
+    ids: Vec<String>,
+) -> Result<Vec<JournalEntry>, String> {
+    let service = state.service()?;
+    service.reorder_pins(&ids).await.map_err(|e| e.to_string())
+}
+
+// Lets the UI hide relevance-only features on SQLite builds without FTS5
+#[tauri::command]
+async fn search_capabilities(state: State<'_, AppState>) -> Result<SearchCapabilities, String> {
+    let service = state.service()?;
+    Ok(service.search_capabilities())
 }
 
 #[tauri::command]
@@ -157,24 +702,150 @@ async fn search_entries(
     state: State<'_, AppState>,
     request: SearchRequest,
 ) -> Result<Vec<JournalEntry>, String> {
-    let db = {
-        let db_guard = state.db.lock().unwrap();
-        db_guard.as_ref().ok_or("Database not initialized")?.clone()
-    };
+    let service = state.service()?;
+    service
+        .search_entries(request)
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    let user_id = state
-        .user_id
-        .lock()
-        .unwrap()
-        .as_ref()
-        .cloned()
-        .ok_or("User not initialized")?;
+// Like search_entries, with the total number of matches
+#[tauri::command]
+async fn search_entries_with_total(
+    state: State<'_, AppState>,
+    request: SearchRequest,
+) -> Result<SearchPage, String> {
+    let service = state.service()?;
+    service
+        .search_entries_with_total(request)
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    let results = db
-        .search_entries(&user_id, request)
+// Scores entry search against a small labeled set: recall@k and MRR
+#[tauri::command]
+async fn evaluate_retrieval(
+    state: State<'_, AppState>,
+    queries: Vec<RetrievalQuery>,
+    k: Option<usize>,
+    title_weight: Option<f64>,
+) -> Result<RetrievalEvaluation, String> {
+    let service = state.service()?;
+    service
+        .evaluate_retrieval(queries, k, title_weight)
         .await
-        .map_err(|e| e.to_string())?;
-    Ok(results)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn search_by_text(
+    state: State<'_, AppState>,
+    text: String,
+    top_k: Option<i32>,
+) -> Result<Vec<JournalEntry>, String> {
+    let service = state.service()?;
+    service
+        .search_by_text(&text, top_k.unwrap_or(10))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn contains_topic(
+    state: State<'_, AppState>,
+    query: String,
+    min_score: Option<f64>,
+) -> Result<TopicCheck, String> {
+    let service = state.service()?;
+    service
+        .contains_topic(&query, min_score)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_entries_by_tag(
+    state: State<'_, AppState>,
+    tag: String,
+) -> Result<Vec<JournalEntry>, String> {
+    let service = state.service()?;
+    service
+        .get_entries_by_tag(&tag)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn list_tags(
+    state: State<'_, AppState>,
+    min_count: Option<i64>,
+) -> Result<Vec<TagCount>, String> {
+    let service = state.service()?;
+    service
+        .list_tags(min_count)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Tags exactly as entered, for the sidebar tag cloud
+#[tauri::command]
+async fn get_tag_counts(state: State<'_, AppState>) -> Result<Vec<TagCount>, String> {
+    let service = state.service()?;
+    service.get_tag_counts().await.map_err(|e| e.to_string())
+}
+
+// Mood counts for a trend chart; the range defaults to all time
+#[tauri::command]
+async fn get_mood_stats(
+    state: State<'_, AppState>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> Result<MoodStats, String> {
+    let service = state.service()?;
+    service
+        .get_mood_stats(from, to)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_tag_cooccurrence(
+    state: State<'_, AppState>,
+    min_count: Option<i64>,
+) -> Result<Vec<TagPair>, String> {
+    let service = state.service()?;
+    service
+        .get_tag_cooccurrence(min_count)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_length_trend(
+    state: State<'_, AppState>,
+    bucket: TrendBucket,
+) -> Result<Vec<LengthTrendPoint>, String> {
+    let service = state.service()?;
+    service
+        .get_length_trend(bucket)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// `date` is YYYY-MM-DD in the user's time zone
+#[tauri::command]
+async fn get_day_summary(
+    state: State<'_, AppState>,
+    date: String,
+    include_recap: Option<bool>,
+) -> Result<DaySummary, String> {
+    let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+        .map_err(|e| format!("Invalid date '{}': {}", date, e))?;
+    let service = state.service()?;
+    service
+        .get_day_summary(date, include_recap.unwrap_or(false))
+        .await
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -182,40 +853,182 @@ async fn chat_with_ai(
     state: State<'_, AppState>,
     request: PythonChatRequest,
 ) -> Result<PythonChatResponse, String> {
-    let db = {
-        let db_guard = state.db.lock().unwrap();
-        db_guard.as_ref().ok_or("Database not initialized")?.clone()
-    };
+    let service = state.service()?;
+    service.chat(request).await.map_err(|e| e.to_string())
+}
 
-    // Store user message
-    let _ = db
-        .create_chat_message(&request.user_id, &request.message, true)
-        .await;
-
-    // Call Python RAG service
-    let client = reqwest::Client::new();
-    let python_request = PythonChatRequest {
-        user_id: request.user_id.clone(),
-        message: request.message.clone(),
-        conversation_id: request.conversation_id.clone(),
-    };
+#[tauri::command]
+async fn regenerate_last_response(
+    state: State<'_, AppState>,
+    conversation_id: String,
+    params: Option<GenerationParams>,
+) -> Result<PythonChatResponse, String> {
+    let service = state.service()?;
+    service
+        .regenerate_last_response(&conversation_id, params.unwrap_or_default())
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    let response = client
-        .post("http://127.0.0.1:8000/chat")
-        .json(&python_request)
-        .send()
+#[tauri::command]
+async fn edit_and_resend(
+    state: State<'_, AppState>,
+    message_id: String,
+    new_content: String,
+) -> Result<PythonChatResponse, String> {
+    let service = state.service()?;
+    service
+        .edit_and_resend(&message_id, &new_content)
         .await
-        .map_err(|e| format!("Failed to connect to Python service: {}", e))?
-        .json::<PythonChatResponse>()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn export_conversation(
+    state: State<'_, AppState>,
+    conversation_id: String,
+    format: ExportFormat,
+) -> Result<String, String> {
+    let service = state.service()?;
+    service
+        .export_conversation(&conversation_id, format)
         .await
-        .map_err(|e| format!("Failed to parse Python response: {}", e))?;
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_python_payload_mapping(
+    state: State<'_, AppState>,
+) -> Result<PythonPayloadMapping, String> {
+    Ok(state.python_payload.lock().unwrap().clone())
+}
+
+#[tauri::command]
+async fn set_python_payload_mapping(
+    state: State<'_, AppState>,
+    mapping: PythonPayloadMapping,
+) -> Result<PythonPayloadMapping, String> {
+    mapping.validate().map_err(|e| e.to_string())?;
+    *state.python_payload.lock().unwrap() = mapping.clone();
+    Ok(mapping)
+}
+
+#[tauri::command]
+async fn get_answer_cleanup(state: State<'_, AppState>) -> Result<AnswerCleanup, String> {
+    Ok(state.answer_cleanup.lock().unwrap().clone())
+}
+
+#[tauri::command]
+async fn set_answer_cleanup(
+    state: State<'_, AppState>,
+    cleanup: AnswerCleanup,
+) -> Result<AnswerCleanup, String> {
+    cleanup.validate().map_err(|e| e.to_string())?;
+    *state.answer_cleanup.lock().unwrap() = cleanup.clone();
+    Ok(cleanup)
+}
+
+#[tauri::command]
+async fn get_transform_settings(state: State<'_, AppState>) -> Result<TransformSettings, String> {
+    Ok(state.transforms.lock().unwrap().clone())
+}
 
-    // Store AI response
-    let _ = db
-        .create_chat_message(&request.user_id, &response.answer, false)
-        .await;
+// Applies to entries saved from now on; existing entries are left alone
+#[tauri::command]
+async fn set_transform_settings(
+    state: State<'_, AppState>,
+    settings: TransformSettings,
+) -> Result<TransformSettings, String> {
+    settings.validate().map_err(|e| e.to_string())?;
+    *state.transforms.lock().unwrap() = settings.clone();
+    Ok(settings)
+}
+
+#[tauri::command]
+async fn get_max_pins(state: State<'_, AppState>) -> Result<Option<u32>, String> {
+    Ok(*state.max_pins.lock().unwrap())
+}
+
+// Lowering the limit keeps existing pins; it only blocks new ones
+#[tauri::command]
+async fn set_max_pins(
+    state: State<'_, AppState>,
+    max_pins: Option<u32>,
+) -> Result<Option<u32>, String> {
+    if max_pins == Some(0) {
+        return Err("Pin limit must be at least 1; leave it unset for no limit".to_string());
+    }
+    *state.max_pins.lock().unwrap() = max_pins;
+    Ok(max_pins)
+}
+
+#[tauri::command]
+async fn get_incremental_vacuum(state: State<'_, AppState>) -> Result<bool, String> {
+    Ok(*state.incremental_vacuum.lock().unwrap())
+}
+
+// Rebuilds the open database in (or out of) incremental auto-vacuum mode,
+// which the file keeps across restarts, and turns the periodic incremental
+// vacuum on or off. Databases created from now on start in the same mode.
+#[tauri::command]
+async fn set_incremental_vacuum(state: State<'_, AppState>, enabled: bool) -> Result<bool, String> {
+    if let Ok(service) = state.service() {
+        let mode = if enabled {
+            AutoVacuum::Incremental
+        } else {
+            AutoVacuum::None
+        };
+        service
+            .set_auto_vacuum(mode)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    *state.incremental_vacuum.lock().unwrap() = enabled;
+    Ok(enabled)
+}
+
+#[tauri::command]
+async fn get_date_display(state: State<'_, AppState>) -> Result<DateDisplay, String> {
+    Ok(state.date_display.lock().unwrap().clone())
+}
+
+#[tauri::command]
+async fn set_date_display(
+    state: State<'_, AppState>,
+    display: DateDisplay,
+) -> Result<DateDisplay, String> {
+    display.validate().map_err(|e| e.to_string())?;
+    *state.date_display.lock().unwrap() = display.clone();
+    Ok(display)
+}
+
+#[tauri::command]
+async fn get_webhook_config(state: State<'_, AppState>) -> Result<WebhookConfig, String> {
+    Ok(state.webhook.lock().unwrap().clone())
+}
 
-    Ok(response)
+#[tauri::command]
+async fn set_webhook_config(
+    state: State<'_, AppState>,
+    config: WebhookConfig,
+) -> Result<WebhookConfig, String> {
+    config.validate().map_err(|e| e.to_string())?;
+    *state.webhook.lock().unwrap() = config.clone();
+    Ok(config)
+}
+
+#[tauri::command]
+async fn get_python_service_url(state: State<'_, AppState>) -> Result<String, String> {
+    Ok(state.python_service_url.lock().unwrap().clone())
+}
+
+// Base address of the RAG service, e.g. http://127.0.0.1:8000; chat
+// requests go to its /chat endpoint
+#[tauri::command]
+async fn set_python_service_url(state: State<'_, AppState>, url: String) -> Result<String, String> {
+    let url = python::normalize_service_url(&url).map_err(|e| e.to_string())?;
+    *state.python_service_url.lock().unwrap() = url.clone();
+    Ok(url)
 }
 
 #[tauri::command]
@@ -228,23 +1041,165 @@ async fn get_system_info() -> Result<serde_json::Value, String> {
     Ok(info)
 }
 
+#[tauri::command]
+async fn get_most_referenced_entries(
+    state: State<'_, AppState>,
+    limit: Option<i32>,
+) -> Result<Vec<ReferencedEntry>, String> {
+    let service = state.service()?;
+    service
+        .get_most_referenced_entries(limit.unwrap_or(20))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_generation_queue_status(
+    state: State<'_, AppState>,
+) -> Result<GenerationQueueStatus, String> {
+    let service = state.service()?;
+    Ok(service.generation_queue_status())
+}
+
+// Emits `chat-cancelled` with the conversation id when something was stopped
+#[tauri::command]
+async fn cancel_generation(
+    state: State<'_, AppState>,
+    app: AppHandle,
+    conversation_id: String,
+) -> Result<bool, String> {
+    let service = state.service()?;
+    let cancelled = service.cancel_generation(&conversation_id);
+    if cancelled {
+        let payload = serde_json::json!({ "conversation_id": conversation_id });
+        if let Err(e) = app.emit("chat-cancelled", payload) {
+            log::warn!("Failed to emit chat cancellation: {}", e);
+        }
+    }
+    Ok(cancelled)
+}
+
 #[tauri::command]
 async fn get_chat_history(state: State<'_, AppState>) -> Result<Vec<ChatMessage>, String> {
-    let db = {
-        let db_guard = state.db.lock().unwrap();
-        db_guard.as_ref().ok_or("Database not initialized")?.clone()
-    };
+    let service = state.service()?;
+    service.get_chat_history().await.map_err(|e| e.to_string())
+}
 
-    let user_id = {
-        let uid_guard = state.user_id.lock().unwrap();
-        uid_guard.clone().ok_or("User not initialized")?
-    };
+#[tauri::command]
+async fn list_conversations(
+    state: State<'_, AppState>,
+) -> Result<Vec<ConversationSummary>, String> {
+    let service = state.service()?;
+    service
+        .list_conversations()
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    let messages = db
-        .get_chat_messages(&user_id, Some(50))
+// Limits a conversation's sources to matching entries; pass no scope to
+// clear it
+#[tauri::command]
+async fn set_conversation_scope(
+    state: State<'_, AppState>,
+    conversation_id: String,
+    scope: Option<ConversationScope>,
+) -> Result<Option<ConversationScope>, String> {
+    let service = state.service()?;
+    service
+        .set_conversation_scope(&conversation_id, scope)
         .await
-        .map_err(|e| e.to_string())?;
-    Ok(messages)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_conversation_scope(
+    state: State<'_, AppState>,
+    conversation_id: String,
+) -> Result<Option<ConversationScope>, String> {
+    let service = state.service()?;
+    service
+        .get_conversation_scope(&conversation_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_personas(state: State<'_, AppState>) -> Result<Vec<Persona>, String> {
+    let service = state.service()?;
+    service.get_personas().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn create_persona(
+    state: State<'_, AppState>,
+    name: String,
+    system_prompt: String,
+) -> Result<Persona, String> {
+    let service = state.service()?;
+    service
+        .create_persona(&name, &system_prompt)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn update_persona(
+    state: State<'_, AppState>,
+    id: String,
+    name: Option<String>,
+    system_prompt: Option<String>,
+) -> Result<Option<Persona>, String> {
+    let service = state.service()?;
+    service
+        .update_persona(&id, name.as_deref(), system_prompt.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn delete_persona(state: State<'_, AppState>, id: String) -> Result<bool, String> {
+    let service = state.service()?;
+    service.delete_persona(&id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_chat_analytics(state: State<'_, AppState>) -> Result<ChatAnalytics, String> {
+    let service = state.service()?;
+    service
+        .get_chat_analytics()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn delete_chat_message(state: State<'_, AppState>, id: String) -> Result<bool, String> {
+    let service = state.service()?;
+    service
+        .delete_chat_message(&id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn bookmark_message(
+    state: State<'_, AppState>,
+    id: String,
+    bookmarked: bool,
+) -> Result<bool, String> {
+    let service = state.service()?;
+    service
+        .bookmark_message(&id, bookmarked)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_bookmarked_messages(state: State<'_, AppState>) -> Result<Vec<ChatMessage>, String> {
+    let service = state.service()?;
+    service
+        .get_bookmarked_messages()
+        .await
+        .map_err(|e| e.to_string())
 }
 
 // Simple greeting command for testing
@@ -253,6 +1208,24 @@ fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
+// Releases free pages of the active database while incremental vacuum is on
+async fn run_incremental_vacuum(app: AppHandle) {
+    let mut interval = tokio::time::interval(INCREMENTAL_VACUUM_INTERVAL);
+    loop {
+        interval.tick().await;
+        let state = app.state::<AppState>();
+        if !*state.incremental_vacuum.lock().unwrap() {
+            continue;
+        }
+        let Ok(service) = state.service() else {
+            continue;
+        };
+        if let Err(e) = service.incremental_vacuum().await {
+            log::warn!("Incremental vacuum failed: {}", e);
+        }
+    }
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -271,19 +1244,101 @@ pub fn run() {
                 let window = app.get_webview_window("main").unwrap();
                 window.open_devtools();
             }
+            tauri::async_runtime::spawn(run_incremental_vacuum(app.handle().clone()));
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             greet,
             initialize_database,
+            switch_profile,
+            list_profiles,
+            reset_user_data,
+            checkpoint_wal,
+            get_database_diagnostics,
+            audit_ownership,
+            rebuild_search_index,
             create_entry,
+            import_directory,
+            pause_import,
+            resume_import,
             get_entries,
+            get_entries_page,
+            get_entries_by_word_count,
+            get_entry_summaries,
             get_entry,
+            get_entries_by_ids,
+            get_entry_history,
+            restore_entry_version,
+            chunk_entry,
+            get_entry_chunks,
+            entry_exists,
+            title_exists,
+            set_entry_field,
+            get_entry_fields,
+            get_entries_where_field,
             update_entry,
+            set_visibility_by_tag,
+            find_anomalous_dates,
+            fix_entry_date,
+            append_to_entry,
+            merge_entries,
+            split_entry,
             delete_entry,
+            restore_entry,
+            get_trashed_entries,
+            purge_entry,
+            pin_entry,
+            unpin_entry,
+            get_pinned_entries,
+            reorder_pins,
+            search_capabilities,
             search_entries,
+            search_entries_with_total,
+            evaluate_retrieval,
+            search_by_text,
+            contains_topic,
+            get_entries_by_tag,
+            list_tags,
+            get_tag_counts,
+            get_mood_stats,
+            get_tag_cooccurrence,
+            get_length_trend,
+            get_day_summary,
             chat_with_ai,
+            regenerate_last_response,
+            edit_and_resend,
+            export_conversation,
+            get_python_payload_mapping,
+            set_python_payload_mapping,
+            get_answer_cleanup,
+            set_answer_cleanup,
+            get_transform_settings,
+            set_transform_settings,
+            get_max_pins,
+            set_max_pins,
+            get_incremental_vacuum,
+            set_incremental_vacuum,
+            get_date_display,
+            set_date_display,
+            get_webhook_config,
+            set_webhook_config,
+            get_python_service_url,
+            set_python_service_url,
+            get_most_referenced_entries,
+            get_generation_queue_status,
+            cancel_generation,
             get_chat_history,
+            list_conversations,
+            get_chat_analytics,
+            set_conversation_scope,
+            get_conversation_scope,
+            get_personas,
+            create_persona,
+            update_persona,
+            delete_persona,
+            delete_chat_message,
+            bookmark_message,
+            get_bookmarked_messages,
             get_system_info
         ])
         .run(tauri::generate_context!())
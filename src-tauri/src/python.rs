@@ -0,0 +1,462 @@
+use anyhow::{anyhow, Result};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::db::ConversationScope;
+
+// Most characters of the message forwarded to the backend by default. No
+// typed question comes close; the cap is there so generated prompts (such
+// as day recaps) and large pastes can't produce oversized requests to a
+// remote backend. Text past the cap is cut off, so a lower cap trades
+// completeness for smaller requests.
+const DEFAULT_MAX_CONTEXT_CHARS: usize = 32_000;
+
+// Where python-rag-service listens when started with its defaults
+pub const DEFAULT_PYTHON_SERVICE_URL: &str = "http://127.0.0.1:8000";
+
+// Checks a service base URL and returns it without a trailing slash, so
+// endpoint paths can be appended to it
+pub fn normalize_service_url(url: &str) -> Result<String> {
+    let trimmed = url.trim().trim_end_matches('/');
+    let parsed = reqwest::Url::parse(trimmed)
+        .map_err(|e| anyhow!("Invalid Python service URL '{}': {}", url, e))?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err(anyhow!("Python service URL must use http or https"));
+    }
+    if parsed.host_str().map_or(true, str::is_empty) {
+        return Err(anyhow!("Python service URL must include a host"));
+    }
+    if parsed.query().is_some() || parsed.fragment().is_some() {
+        return Err(anyhow!(
+            "Python service URL must be a base address without a query or fragment"
+        ));
+    }
+    Ok(trimmed.to_string())
+}
+
+// The chat endpoint under a base URL
+pub fn chat_url(base: &str) -> String {
+    format!("{}/chat", base.trim_end_matches('/'))
+}
+
+// Python RAG Service integration
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PythonChatRequest {
+    pub user_id: String,
+    pub message: String,
+    pub conversation_id: Option<String>,
+    pub answer_style: Option<AnswerStyle>,
+    // Saved persona whose instructions the answer should follow
+    pub persona_id: Option<String>,
+    // Save the question and answer to chat history; defaults to true.
+    // Incognito questions still search the journal, they just leave no trace.
+    pub persist: Option<bool>,
+    // Cap on the message characters forwarded; defaults to
+    // DEFAULT_MAX_CONTEXT_CHARS
+    pub max_context_chars: Option<usize>,
+}
+
+// Generation settings a regenerated answer may change; unset ones fall back
+// to the defaults, as for a new question
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GenerationParams {
+    pub answer_style: Option<AnswerStyle>,
+    pub persona_id: Option<String>,
+    pub max_context_chars: Option<usize>,
+}
+
+// What was actually forwarded for a request, so callers can tell when
+// their message was cut to fit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForwardedContext {
+    pub max_context_chars: usize,
+    pub forwarded_chars: usize,
+    pub truncated: bool,
+}
+
+// How long and in what shape the assistant should answer. The prompt itself
+// is built by the Python service, so the style travels as an instruction
+// appended to the question plus a `max_tokens` hint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnswerStyle {
+    #[default]
+    Concise,
+    Detailed,
+    Bullets,
+    Reflective,
+}
+
+impl AnswerStyle {
+    // Concise adds nothing, keeping the backend's own prompt unchanged
+    pub fn instruction(self) -> Option<&'static str> {
+        match self {
+            AnswerStyle::Concise => None,
+            AnswerStyle::Detailed => Some(
+                "Answer in detail, drawing on the specific journal entries that are relevant.",
+            ),
+            AnswerStyle::Bullets => Some("Answer as a short list of bullet points."),
+            AnswerStyle::Reflective => Some(
+                "Answer reflectively: describe the patterns and feelings you notice across the entries and end with one gentle follow-up question.",
+            ),
+        }
+    }
+
+    pub fn max_tokens(self) -> u32 {
+        match self {
+            AnswerStyle::Concise | AnswerStyle::Bullets => 512,
+            AnswerStyle::Detailed | AnswerStyle::Reflective => 1024,
+        }
+    }
+}
+
+// Labels some models echo from their prompt at the start of an answer
+// ("Assistant: Sure, ..."). Only a label opening the answer is stripped, so
+// the same words later in the text are left alone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnswerCleanup {
+    // Matched case-insensitively, e.g. "Assistant:"
+    pub leaked_prefixes: Vec<String>,
+}
+
+impl Default for AnswerCleanup {
+    fn default() -> Self {
+        AnswerCleanup {
+            leaked_prefixes: ["System:", "Assistant:", "AI:"]
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl AnswerCleanup {
+    pub fn validate(&self) -> Result<()> {
+        if self.leaked_prefixes.iter().any(|p| p.trim().is_empty()) {
+            return Err(anyhow!("Leaked prefixes must not be empty"));
+        }
+        Ok(())
+    }
+
+    // Strips leading whitespace and any run of leaked labels before the
+    // actual answer. An answer that is nothing but labels is kept as is.
+    pub fn apply(&self, answer: &str) -> String {
+        let mut rest = answer.trim_start();
+        while let Some(stripped) = self.strip_prefix(rest) {
+            rest = stripped.trim_start();
+        }
+        if rest.is_empty() {
+            return answer.trim().to_string();
+        }
+        rest.to_string()
+    }
+
+    fn strip_prefix<'a>(&self, text: &'a str) -> Option<&'a str> {
+        self.leaked_prefixes.iter().find_map(|prefix| {
+            let prefix = prefix.trim();
+            text.get(..prefix.len())
+                .filter(|head| head.eq_ignore_ascii_case(prefix))
+                .map(|_| &text[prefix.len()..])
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PythonChatResponse {
+    pub answer: String,
+    pub sources: Vec<serde_json::Value>,
+    pub conversation_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<ForwardedContext>,
+    // Time the service took to answer, not counting time spent queued
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generation_ms: Option<u64>,
+    // The conversation's scope, when its sources were limited by one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<ConversationScope>,
+    // Set when the service couldn't be reached and `sources` are local
+    // search matches rather than a generated answer's sources
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub offline: bool,
+}
+
+// The chat service couldn't be connected to at all, as opposed to failing
+// or replying with something unusable
+#[derive(Debug)]
+pub struct ServiceUnreachable(pub String);
+
+impl std::fmt::Display for ServiceUnreachable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to connect to Python service: {}", self.0)
+    }
+}
+
+impl std::error::Error for ServiceUnreachable {}
+
+// Field names used on the wire when talking to a Python RAG backend.
+// Backends disagree on naming (`message` vs `query`, `user_id` vs `userId`),
+// so the mapping is configurable. The default matches python-rag-service.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PythonPayloadMapping {
+    pub user_id_field: String,
+    pub message_field: String,
+    pub conversation_id_field: String,
+    pub max_tokens_field: String,
+    pub scope_field: String,
+    pub answer_field: String,
+    pub sources_field: String,
+    pub response_conversation_id_field: String,
+}
+
+impl Default for PythonPayloadMapping {
+    fn default() -> Self {
+        PythonPayloadMapping {
+            user_id_field: "user_id".to_string(),
+            message_field: "message".to_string(),
+            conversation_id_field: "conversation_id".to_string(),
+            max_tokens_field: "max_tokens".to_string(),
+            scope_field: "scope".to_string(),
+            answer_field: "answer".to_string(),
+            sources_field: "sources".to_string(),
+            response_conversation_id_field: "conversation_id".to_string(),
+        }
+    }
+}
+
+impl PythonPayloadMapping {
+    pub fn validate(&self) -> Result<()> {
+        let fields = [
+            ("user_id_field", &self.user_id_field),
+            ("message_field", &self.message_field),
+            ("conversation_id_field", &self.conversation_id_field),
+            ("max_tokens_field", &self.max_tokens_field),
+            ("scope_field", &self.scope_field),
+            ("answer_field", &self.answer_field),
+            ("sources_field", &self.sources_field),
+            (
+                "response_conversation_id_field",
+                &self.response_conversation_id_field,
+            ),
+        ];
+        for (name, value) in fields {
+            if value.trim().is_empty() {
+                return Err(anyhow!(
+                    "Payload mapping field '{}' must not be empty",
+                    name
+                ));
+            }
+        }
+
+        let request_fields = [
+            &self.user_id_field,
+            &self.message_field,
+            &self.conversation_id_field,
+            &self.max_tokens_field,
+            &self.scope_field,
+        ];
+        for (i, field) in request_fields.iter().enumerate() {
+            if request_fields[i + 1..].contains(field) {
+                return Err(anyhow!(
+                    "Payload mapping uses '{}' for more than one request field",
+                    field
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // `scope` limits which entries the backend retrieves from; the zone
+    // decides which day an entry without an explicit date falls on
+    pub fn build_request(
+        &self,
+        request: &PythonChatRequest,
+        persona_prompt: Option<&str>,
+        scope: Option<(&ConversationScope, Tz)>,
+    ) -> (Value, ForwardedContext) {
+        let max_context_chars = request
+            .max_context_chars
+            .unwrap_or(DEFAULT_MAX_CONTEXT_CHARS);
+        let (message, truncated) = match request.message.char_indices().nth(max_context_chars) {
+            Some((end, _)) => (&request.message[..end], true),
+            None => (request.message.as_str(), false),
+        };
+        let context = ForwardedContext {
+            max_context_chars,
+            forwarded_chars: message.chars().count(),
+            truncated,
+        };
+
+        // Like the answer style, a persona travels as an instruction after
+        // the question since the backend owns the actual system prompt
+        let style = request.answer_style.unwrap_or_default();
+        let mut message = message.to_string();
+        for instruction in persona_prompt.into_iter().chain(style.instruction()) {
+            message.push_str(&format!("\n\n({})", instruction));
+        }
+
+        let mut body = Map::new();
+        body.insert(
+            self.user_id_field.clone(),
+            Value::String(request.user_id.clone()),
+        );
+        body.insert(self.message_field.clone(), Value::String(message));
+        body.insert(
+            self.conversation_id_field.clone(),
+            request
+                .conversation_id
+                .clone()
+                .map(Value::String)
+                .unwrap_or(Value::Null),
+        );
+        body.insert(
+            self.max_tokens_field.clone(),
+            Value::from(style.max_tokens()),
+        );
+        if let Some((scope, tz)) = scope {
+            body.insert(
+                self.scope_field.clone(),
+                json!({
+                    "tags": scope.tags,
+                    "date_from": scope.date_from,
+                    "date_to": scope.date_to,
+                    "timezone": tz.name(),
+                }),
+            );
+        }
+        (Value::Object(body), context)
+    }
+
+    // Validates a raw backend reply against the configured response shape.
+    // `answer` is required; `sources` and the conversation id may be omitted,
+    // but must have the right type when present.
+    pub fn parse_response(
+        &self,
+        response: Value,
+        request_conversation_id: Option<&str>,
+    ) -> Result<PythonChatResponse> {
+        let mut body = match response {
+            Value::Object(map) => map,
+            other => {
+                return Err(anyhow!(
+                    "Python response must be a JSON object, got {}",
+                    json_type_name(&other)
+                ))
+            }
+        };
+
+        let answer = match body.remove(&self.answer_field) {
+            Some(Value::String(answer)) => answer,
+            Some(other) => {
+                return Err(anyhow!(
+                    "Python response field '{}' must be a string, got {}",
+                    self.answer_field,
+                    json_type_name(&other)
+                ))
+            }
+            None => {
+                return Err(anyhow!(
+                    "Python response is missing the '{}' field",
+                    self.answer_field
+                ))
+            }
+        };
+
+        let sources = match body.remove(&self.sources_field) {
+            Some(Value::Array(sources)) => sources,
+            Some(Value::Null) | None => Vec::new(),
+            Some(other) => {
+                return Err(anyhow!(
+                    "Python response field '{}' must be an array, got {}",
+                    self.sources_field,
+                    json_type_name(&other)
+                ))
+            }
+        };
+
+        let conversation_id = match body.remove(&self.response_conversation_id_field) {
+            Some(Value::String(id)) => id,
+            Some(Value::Null) | None => request_conversation_id.unwrap_or("default").to_string(),
+            Some(other) => {
+                return Err(anyhow!(
+                    "Python response field '{}' must be a string, got {}",
+                    self.response_conversation_id_field,
+                    json_type_name(&other)
+                ))
+            }
+        };
+
+        Ok(PythonChatResponse {
+            answer,
+            sources,
+            conversation_id,
+            context: None,
+            generation_ms: None,
+            scope: None,
+            offline: false,
+        })
+    }
+}
+
+fn json_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaked_assistant_prefix_is_stripped() {
+        let cleanup = AnswerCleanup::default();
+        assert_eq!(
+            cleanup.apply("  Assistant: You slept better this week."),
+            "You slept better this week."
+        );
+        // Only a label opening the answer counts
+        assert_eq!(
+            cleanup.apply("Ask the Assistant: it knows."),
+            "Ask the Assistant: it knows."
+        );
+    }
+
+    #[test]
+    fn scope_is_sent_with_the_zone_that_dates_entries() {
+        let mapping = PythonPayloadMapping::default();
+        let request = PythonChatRequest {
+            user_id: "u".to_string(),
+            message: "How was the trip?".to_string(),
+            ..Default::default()
+        };
+        let scope = ConversationScope {
+            tags: vec!["travel".to_string()],
+            date_from: chrono::NaiveDate::from_ymd_opt(2024, 5, 1),
+            date_to: None,
+        };
+
+        let (body, _) = mapping.build_request(&request, None, Some((&scope, Tz::Europe__Berlin)));
+        assert_eq!(
+            body["scope"],
+            json!({
+                "tags": ["travel"],
+                "date_from": "2024-05-01",
+                "date_to": null,
+                "timezone": "Europe/Berlin",
+            })
+        );
+
+        // Unscoped conversations send no scope at all
+        let (body, _) = mapping.build_request(&request, None, None);
+        assert!(body.get("scope").is_none());
+    }
+}
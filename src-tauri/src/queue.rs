@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, Semaphore};
+
+// Assumed generation time until a real one has been measured
+const INITIAL_ESTIMATE_MS: u64 = 10_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationQueueStatus {
+    pub running: bool,
+    pub waiting: usize,
+    // Rough time until a request submitted now would start
+    pub estimated_wait_secs: f64,
+}
+
+// Runs generations one at a time. The model behind the RAG service can only
+// serve one answer at a time, so extra requests wait here in FIFO order, up
+// to `max_waiting`, instead of piling onto it.
+pub struct GenerationQueue {
+    permit: Semaphore,
+    waiting: AtomicUsize,
+    max_waiting: usize,
+    // Moving average of recent generation times
+    average_ms: AtomicU64,
+    // Conversations with a generation running or queued: the signal that
+    // cancels them, and how many there are
+    active: Mutex<HashMap<String, (Arc<Notify>, usize)>>,
+}
+
+// Keeps the waiting count right even if a queued request is cancelled
+struct WaitingGuard<'a>(&'a AtomicUsize);
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Unregisters a generation from `active` however it ends
+struct ActiveGuard<'a> {
+    active: &'a Mutex<HashMap<String, (Arc<Notify>, usize)>>,
+    conversation_id: &'a str,
+}
+
+impl Drop for ActiveGuard<'_> {
+    fn drop(&mut self) {
+        let mut active = self.active.lock().unwrap();
+        if let Some((_, count)) = active.get_mut(self.conversation_id) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(self.conversation_id);
+            }
+        }
+    }
+}
+
+impl GenerationQueue {
+    pub fn new(max_waiting: usize) -> Self {
+        GenerationQueue {
+            permit: Semaphore::new(1),
+            waiting: AtomicUsize::new(0),
+            max_waiting,
+            average_ms: AtomicU64::new(INITIAL_ESTIMATE_MS),
+            active: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Runs `generation` for a conversation, unless `cancel` is called for
+    // that conversation first, whether it's still queued or already running
+    pub async fn run<T>(
+        &self,
+        conversation_id: &str,
+        generation: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let cancelled = {
+            let mut active = self.active.lock().unwrap();
+            let (signal, count) = active
+                .entry(conversation_id.to_string())
+                .or_insert_with(|| (Arc::new(Notify::new()), 0));
+            *count += 1;
+            signal.clone()
+        };
+        let _active = ActiveGuard {
+            active: &self.active,
+            conversation_id,
+        };
+
+        tokio::select! {
+            result = self.run_queued(generation) => result,
+            _ = cancelled.notified() => Err(anyhow!(
+                "Cancelled: the response for conversation '{}' was stopped",
+                conversation_id
+            )),
+        }
+    }
+
+    // Stops every generation running or queued for the conversation.
+    // Returns whether there was one.
+    pub fn cancel(&self, conversation_id: &str) -> bool {
+        match self.active.lock().unwrap().get(conversation_id) {
+            Some((signal, _)) => {
+                signal.notify_waiters();
+                true
+            }
+            None => false,
+        }
+    }
+
+    async fn run_queued<T>(
+        &self,
+        generation: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let _permit = match self.permit.try_acquire() {
+            Ok(permit) => permit,
+            Err(_) => {
+                let ahead = self.waiting.fetch_add(1, Ordering::SeqCst);
+                let _waiting = WaitingGuard(&self.waiting);
+                if ahead >= self.max_waiting {
+                    return Err(anyhow!(
+                        "Busy: {} requests are already waiting for the assistant",
+                        ahead
+                    ));
+                }
+                self.permit.acquire().await?
+            }
+        };
+
+        let started = Instant::now();
+        let result = generation.await;
+        self.record(started.elapsed());
+        result
+    }
+
+    pub fn status(&self) -> GenerationQueueStatus {
+        let running = self.permit.available_permits() == 0;
+        let waiting = self.waiting.load(Ordering::SeqCst);
+        let ahead = waiting + usize::from(running);
+        let average = Duration::from_millis(self.average_ms.load(Ordering::SeqCst));
+
+        GenerationQueueStatus {
+            running,
+            waiting,
+            estimated_wait_secs: average.as_secs_f64() * ahead as f64,
+        }
+    }
+
+    fn record(&self, elapsed: Duration) {
+        let elapsed_ms = elapsed.as_millis() as u64;
+        let _ = self
+            .average_ms
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |average| {
+                Some((average * 3 + elapsed_ms) / 4)
+            });
+    }
+}
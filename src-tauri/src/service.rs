@@ -0,0 +1,1480 @@
+use crate::dates::DateDisplay;
+use crate::db::{
+    word_count, AutoVacuum, ChatAnalytics, ChatMessage, ChunkOptions, ConversationScope,
+    ConversationSummary, CreateEntryRequest, Database, DatabaseDiagnostics, DateAnomaly, EntryPage,
+    EntrySummary, EntryVersion, FieldOp, FieldType, JournalEntry, MergeEntriesRequest, OrphanRow,
+    Persona, ReferencedEntry, ResetCounts, SearchCapabilities, SearchPage, SearchRequest,
+    SplitEntryRequest, TagCount, TagPair, TextChunk, TopicCheck, UpdateEntryRequest, Visibility,
+    WalCheckpoint,
+};
+use crate::export::{self, ExportFormat};
+use crate::import::{self, ImportControl, ImportFileResult, ImportProgress, ImportReport};
+use crate::insights::{
+    self, DaySummary, LengthTrendPoint, MoodStats, RetrievalEvaluation, RetrievalQuery, TrendBucket,
+};
+use crate::python::{
+    self, AnswerCleanup, GenerationParams, PythonChatRequest, PythonChatResponse,
+    PythonPayloadMapping, ServiceUnreachable,
+};
+use crate::queue::{GenerationQueue, GenerationQueueStatus};
+use crate::transform::{BodyTransform, TransformPipeline, TransformSettings};
+use crate::webhook::{EntryEvent, WebhookConfig, WebhookSender};
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use uuid::Uuid;
+
+const DEFAULT_USER_EMAIL: &str = "default@journal.app";
+// Matches the id the Python service reports when none is supplied
+const DEFAULT_CONVERSATION_ID: &str = "default";
+// Typed by the user to confirm wiping their data
+const RESET_CONFIRMATION: &str = "DELETE MY JOURNAL";
+// Chat requests beyond this many waiting are rejected as busy
+const MAX_QUEUED_GENERATIONS: usize = 4;
+const DEFAULT_ENTRY_SEPARATOR: &str = "\n\n";
+// Files imported between progress reports
+const IMPORT_BATCH_SIZE: usize = 25;
+// Most characters of each entry included when asking for a day's recap
+const RECAP_ENTRY_CHARS: usize = 2000;
+// Results scored per query by evaluate_retrieval unless asked otherwise
+const DEFAULT_EVALUATION_K: usize = 10;
+// Entries offered as sources when the chat service can't be reached
+const OFFLINE_SOURCE_LIMIT: i32 = 5;
+
+#[derive(Debug, Clone, Default)]
+pub struct ServiceConfig {
+    pub python_payload: PythonPayloadMapping,
+    pub date_display: DateDisplay,
+    pub webhook: WebhookConfig,
+    pub answer_cleanup: AnswerCleanup,
+    // Most entries that may be pinned at once; `None` means no limit
+    pub max_pins: Option<u32>,
+    // Create new databases in incremental auto-vacuum mode
+    pub incremental_vacuum: bool,
+    // Built-in rewrites applied to entry bodies on save
+    pub transforms: TransformSettings,
+    // Base URL of the chat service; `None` uses python::DEFAULT_PYTHON_SERVICE_URL
+    pub python_service_url: Option<String>,
+}
+
+// Core journal operations, independent of Tauri. The command handlers in
+// lib.rs are thin wrappers around these methods, and other frontends (tests,
+// a CLI) can drive the same logic directly.
+#[derive(Clone)]
+pub struct Service {
+    db: Database,
+    user_id: String,
+    config: ServiceConfig,
+    // Shared by every clone, so all chat paths take turns
+    generation: Arc<GenerationQueue>,
+    webhook: Arc<WebhookSender>,
+    import_control: Arc<ImportControl>,
+    // Day recaps by date, with the entry versions they were written from
+    recaps: Arc<Mutex<HashMap<NaiveDate, (String, String)>>>,
+    // Run after the built-in transforms; see with_transform
+    custom_transforms: TransformPipeline,
+}
+
+impl Service {
+    pub fn new(db: Database, user_id: String, config: ServiceConfig) -> Self {
+        Service {
+            db,
+            user_id,
+            config,
+            generation: Arc::new(GenerationQueue::new(MAX_QUEUED_GENERATIONS)),
+            webhook: Arc::new(WebhookSender::default()),
+            import_control: Arc::new(ImportControl::default()),
+            recaps: Arc::new(Mutex::new(HashMap::new())),
+            custom_transforms: TransformPipeline::default(),
+        }
+    }
+
+    // Opens (creating if needed) the database at `database_url` and resolves
+    // the default user.
+    pub async fn connect(database_url: &str, config: ServiceConfig) -> Result<Self> {
+        let db = Database::open(database_url, config.incremental_vacuum).await?;
+        let user_id = db.get_or_create_user(DEFAULT_USER_EMAIL).await?;
+        log::info!("Default user ID: {}", user_id);
+        Ok(Service::new(db, user_id, config))
+    }
+
+    pub fn with_config(mut self, config: ServiceConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    // Adds a body transform of your own, run on save after the enabled
+    // built-ins
+    pub fn with_transform(mut self, transform: Arc<dyn BodyTransform>) -> Self {
+        self.custom_transforms.push(transform);
+        self
+    }
+
+    pub fn db(&self) -> &Database {
+        &self.db
+    }
+
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    // Wipes the user's entries and chat history. When the user row is
+    // deleted too, a fresh default user is created and this service switches
+    // to it, so the caller should keep the updated service and the frontend
+    // the returned user id.
+    pub async fn reset_user_data(
+        &mut self,
+        confirm_token: &str,
+        delete_user: bool,
+    ) -> Result<ResetCounts> {
+        if confirm_token != RESET_CONFIRMATION {
+            return Err(anyhow!(
+                "Type '{}' to confirm resetting your data",
+                RESET_CONFIRMATION
+            ));
+        }
+
+        let mut counts = self.db.reset_user_data(&self.user_id, delete_user).await?;
+
+        // TODO: Clear the RAG index when we implement thread-safe LLM handling
+
+        if counts.user_deleted {
+            self.user_id = self.db.get_or_create_user(DEFAULT_USER_EMAIL).await?;
+            log::info!("Default user ID: {}", self.user_id);
+            counts.user_id = self.user_id.clone();
+        }
+
+        Ok(counts)
+    }
+
+    pub async fn incremental_vacuum(&self) -> Result<()> {
+        self.db.incremental_vacuum().await
+    }
+
+    pub async fn set_auto_vacuum(&self, mode: AutoVacuum) -> Result<()> {
+        self.db.set_auto_vacuum(mode).await
+    }
+
+    pub async fn database_diagnostics(&self) -> Result<DatabaseDiagnostics> {
+        self.db.diagnostics().await
+    }
+
+    pub async fn checkpoint_wal(&self) -> Result<WalCheckpoint> {
+        self.db.checkpoint_wal().await
+    }
+
+    pub async fn audit_ownership(&self) -> Result<BTreeMap<String, Vec<OrphanRow>>> {
+        self.db.audit_ownership().await
+    }
+
+    pub async fn rebuild_search_index(&self) -> Result<()> {
+        self.db.rebuild_search_index().await
+    }
+
+    pub async fn create_entry(&self, mut request: CreateEntryRequest) -> Result<JournalEntry> {
+        // A retried create returns the original entry without announcing it again
+        if let Some(client_id) = &request.client_id {
+            if let Some(existing) = self
+                .db
+                .get_entry_by_client_id(&self.user_id, client_id)
+                .await?
+            {
+                return Ok(existing);
+            }
+        }
+
+        let original = self.transform_body(&mut request.body);
+        let entry = self.db.create_entry(&self.user_id, request).await?;
+        if let Some(original) = original {
+            self.save_original(&entry, &original).await?;
+        }
+
+        // TODO: Index the entry for RAG when we implement thread-safe LLM handling
+
+        self.notify(EntryEvent::Created, &entry);
+        Ok(entry)
+    }
+
+    // Creates an entry per dated text file under `dir`. A file that can't be
+    // imported is reported and skipped rather than aborting the import;
+    // `on_progress` is called after each batch. `entries_per_second` paces
+    // the inserts so a large import leaves room for interactive use.
+    // `date_formats` replaces the default filename date formats; a file
+    // whose name matches none is still imported, dated now, with a warning.
+    pub async fn import_directory(
+        &self,
+        dir: &Path,
+        entries_per_second: Option<f64>,
+        date_formats: Option<Vec<String>>,
+        mut on_progress: impl FnMut(ImportProgress),
+    ) -> Result<ImportReport> {
+        let throttle = import::throttle_interval(entries_per_second)?;
+        let date_formats = date_formats.unwrap_or_else(import::default_date_formats);
+        import::validate_date_formats(&date_formats)?;
+        let (files, skipped) = import::collect_text_files(dir)?;
+        let tz = self.config.date_display.tz()?;
+        // A pause left over from an earlier import shouldn't hold this one
+        self.import_control.resume();
+        let mut pacer = throttle.map(|period| {
+            let mut pacer = tokio::time::interval(period);
+            pacer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            pacer
+        });
+        let mut report = ImportReport {
+            skipped,
+            ..Default::default()
+        };
+
+        for batch in files.chunks(IMPORT_BATCH_SIZE) {
+            for path in batch {
+                self.import_control.wait_while_paused().await;
+                if let Some(pacer) = &mut pacer {
+                    pacer.tick().await;
+                }
+
+                let mut dated = false;
+                let result = match import::read_note(path, tz, &date_formats) {
+                    Ok(note) => {
+                        dated = note.created_at.is_some();
+                        self.create_entry(CreateEntryRequest {
+                            title: note.title,
+                            body: note.body,
+                            created_at: note.created_at,
+                            ..Default::default()
+                        })
+                        .await
+                    }
+                    Err(e) => Err(e),
+                };
+
+                let mut warning = None;
+                let (entry_id, error) = match result {
+                    Ok(entry) => {
+                        report.imported += 1;
+                        if dated {
+                            report.dates_parsed += 1;
+                        } else {
+                            report.dates_defaulted += 1;
+                            warning = Some(
+                                "No date found in the filename; dated at import time".to_string(),
+                            );
+                        }
+                        (Some(entry.id), None)
+                    }
+                    Err(e) => {
+                        report.failed += 1;
+                        (None, Some(e.to_string()))
+                    }
+                };
+                report.files.push(ImportFileResult {
+                    path: path.display().to_string(),
+                    entry_id,
+                    error,
+                    warning,
+                });
+            }
+
+            on_progress(ImportProgress {
+                processed: report.files.len(),
+                total: files.len(),
+                imported: report.imported,
+                failed: report.failed,
+            });
+        }
+
+        Ok(report)
+    }
+
+    // Pauses a running import between files; it picks up where it left off
+    // on resume
+    pub fn pause_import(&self) {
+        self.import_control.pause();
+    }
+
+    pub fn resume_import(&self) {
+        self.import_control.resume();
+    }
+
+    pub async fn get_entries(&self) -> Result<Vec<JournalEntry>> {
+        let tz = self.config.date_display.tz()?;
+        self.db.get_entries(&self.user_id, tz).await
+    }
+
+    pub async fn get_entries_page(&self, limit: i64, offset: i64) -> Result<EntryPage> {
+        let tz = self.config.date_display.tz()?;
+        let entries = self
+            .db
+            .get_entries_paged(&self.user_id, tz, limit, offset)
+            .await?;
+        let total = self.db.count_entries(&self.user_id).await?;
+        Ok(EntryPage { entries, total })
+    }
+
+    pub async fn get_entries_by_word_count(
+        &self,
+        min_words: Option<i64>,
+        max_words: Option<i64>,
+    ) -> Result<Vec<JournalEntry>> {
+        let tz = self.config.date_display.tz()?;
+        self.db
+            .get_entries_by_word_count(&self.user_id, tz, min_words, max_words)
+            .await
+    }
+
+    pub async fn get_entry_summaries(&self) -> Result<Vec<EntrySummary>> {
+        let tz = self.config.date_display.tz()?;
+        self.db.get_entry_summaries(&self.user_id, tz).await
+    }
+
+    pub async fn get_entry_history(&self, entry_id: &str) -> Result<Vec<EntryVersion>> {
+        self.db.get_entry_history(&self.user_id, entry_id).await
+    }
+
+    // Re-chunks an entry's current body; None when the entry doesn't exist
+    // or is in the trash
+    pub async fn chunk_entry(
+        &self,
+        id: &str,
+        options: ChunkOptions,
+    ) -> Result<Option<Vec<TextChunk>>> {
+        let Some(entry) = self.db.get_entry(id).await? else {
+            return Ok(None);
+        };
+        if entry.user_id != self.user_id || entry.deleted_at.is_some() {
+            return Ok(None);
+        }
+        if entry.visibility != Visibility::Normal {
+            return Err(anyhow!("Entries kept out of AI features can't be chunked"));
+        }
+        let chunks = self
+            .db
+            .create_text_chunks(&entry.id, &entry.user_id, &entry.body, options)
+            .await?;
+        Ok(Some(chunks))
+    }
+
+    pub async fn get_entry_chunks(&self, id: &str) -> Result<Vec<TextChunk>> {
+        self.db.get_chunks_for_entry(&self.user_id, id).await
+    }
+
+    pub async fn get_entry(&self, id: &str) -> Result<Option<JournalEntry>> {
+        let Some(mut entry) = self.db.get_entry(id).await? else {
+            return Ok(None);
+        };
+        entry.fields = Some(self.db.get_entry_fields(&self.user_id, id).await?);
+        Ok(Some(entry))
+    }
+
+    pub async fn entry_exists(&self, id: &str) -> Result<bool> {
+        self.db.entry_exists(&self.user_id, id).await
+    }
+
+    pub async fn title_exists(&self, title: &str) -> Result<bool> {
+        self.db.title_exists(&self.user_id, title).await
+    }
+
+    pub async fn get_entries_by_ids(&self, ids: &[String]) -> Result<Vec<JournalEntry>> {
+        self.db.get_entries_by_ids(&self.user_id, ids).await
+    }
+
+    // Every entry with its custom fields loaded, for exports. Entries that
+    // aren't `Normal` are left out unless `include_hidden` is set.
+    pub async fn get_entries_with_fields(&self, include_hidden: bool) -> Result<Vec<JournalEntry>> {
+        let tz = self.config.date_display.tz()?;
+        let mut entries = self.db.get_entries(&self.user_id, tz).await?;
+        entries.retain(|entry| include_hidden || entry.visibility == Visibility::Normal);
+        let mut fields = self.db.get_all_entry_fields(&self.user_id).await?;
+        for entry in &mut entries {
+            entry.fields = fields.remove(&entry.id);
+        }
+        Ok(entries)
+    }
+
+    pub async fn set_entry_field(
+        &self,
+        entry_id: &str,
+        key: &str,
+        value: &serde_json::Value,
+        value_type: FieldType,
+    ) -> Result<bool> {
+        self.db
+            .set_entry_field(&self.user_id, entry_id, key, value, value_type)
+            .await
+    }
+
+    pub async fn get_entry_fields(
+        &self,
+        entry_id: &str,
+    ) -> Result<BTreeMap<String, serde_json::Value>> {
+        self.db.get_entry_fields(&self.user_id, entry_id).await
+    }
+
+    pub async fn get_entries_where_field(
+        &self,
+        key: &str,
+        op: FieldOp,
+        value: &serde_json::Value,
+        value_type: FieldType,
+    ) -> Result<Vec<JournalEntry>> {
+        self.db
+            .get_entries_where_field(&self.user_id, key, op, value, value_type)
+            .await
+    }
+
+    pub async fn update_entry(
+        &self,
+        mut request: UpdateEntryRequest,
+    ) -> Result<Option<JournalEntry>> {
+        let original = match &mut request.body {
+            Some(body) => self.transform_body(body),
+            None => None,
+        };
+        let entry = self.db.update_entry(request).await?;
+        if let (Some(entry), Some(original)) = (&entry, original) {
+            self.save_original(entry, &original).await?;
+        }
+
+        // TODO: Re-index the entry for RAG when we implement thread-safe LLM handling
+
+        if let Some(entry) = &entry {
+            self.notify(EntryEvent::Updated, entry);
+        }
+        Ok(entry)
+    }
+
+    // Puts an entry back the way a saved version had it. The current text
+    // is saved as a version first, so a restore can itself be undone.
+    // Save-time transforms don't run, and a version without a mood leaves
+    // the current mood in place.
+    pub async fn restore_entry_version(&self, version_id: &str) -> Result<Option<JournalEntry>> {
+        let Some(version) = self.db.get_entry_version(&self.user_id, version_id).await? else {
+            return Ok(None);
+        };
+        let entry = self
+            .db
+            .update_entry(UpdateEntryRequest {
+                id: version.entry_id,
+                title: Some(version.title),
+                body: Some(version.body),
+                mood: version.mood,
+                tags: Some(version.tags.unwrap_or_default()),
+                ..Default::default()
+            })
+            .await?;
+
+        if let Some(entry) = &entry {
+            self.notify(EntryEvent::Updated, entry);
+        }
+        Ok(entry)
+    }
+
+    // Excluding entries takes effect on the next answer, since sources
+    // are checked against visibility as they come back from the service.
+    // Returns the number of entries changed.
+    pub async fn set_visibility_by_tag(&self, tag: &str, visibility: Visibility) -> Result<usize> {
+        let ids = self
+            .db
+            .set_visibility_by_tag(&self.user_id, tag, visibility)
+            .await?;
+        for entry in self.db.get_entries_by_ids(&self.user_id, &ids).await? {
+            self.notify(EntryEvent::Updated, &entry);
+        }
+        Ok(ids.len())
+    }
+
+    pub async fn find_anomalous_dates(&self) -> Result<Vec<DateAnomaly>> {
+        let tz = self.config.date_display.tz()?;
+        self.db.find_anomalous_dates(&self.user_id, tz).await
+    }
+
+    pub async fn fix_entry_date(
+        &self,
+        id: &str,
+        created_at: DateTime<Utc>,
+    ) -> Result<Option<JournalEntry>> {
+        let entry = self
+            .db
+            .set_entry_created_at(&self.user_id, id, created_at)
+            .await?;
+
+        if let Some(entry) = &entry {
+            self.notify(EntryEvent::Updated, entry);
+        }
+        Ok(entry)
+    }
+
+    pub async fn append_to_entry(
+        &self,
+        id: &str,
+        text: &str,
+        separator: Option<&str>,
+    ) -> Result<Option<JournalEntry>> {
+        let entry = self
+            .db
+            .append_to_entry(id, text, separator.unwrap_or(DEFAULT_ENTRY_SEPARATOR))
+            .await?;
+
+        // TODO: Re-index the entry for RAG when we implement thread-safe LLM handling
+
+        if let Some(entry) = &entry {
+            self.notify(EntryEvent::Updated, entry);
+        }
+        Ok(entry)
+    }
+
+    pub async fn merge_entries(&self, request: MergeEntriesRequest) -> Result<JournalEntry> {
+        let entry = self
+            .db
+            .merge_entries(
+                &self.user_id,
+                &request.into_id,
+                &request.from_ids,
+                request
+                    .separator
+                    .as_deref()
+                    .unwrap_or(DEFAULT_ENTRY_SEPARATOR),
+                request.union_tags.unwrap_or(true),
+            )
+            .await?;
+
+        // TODO: Re-index the merged entry and drop the merged-from entries
+        // from RAG when we implement thread-safe LLM handling
+
+        self.notify(EntryEvent::Updated, &entry);
+        for id in &request.from_ids {
+            self.webhook.entry_deleted(&self.config.webhook, id);
+        }
+        Ok(entry)
+    }
+
+    pub async fn split_entry(&self, request: SplitEntryRequest) -> Result<Vec<JournalEntry>> {
+        let keep_original = request.keep_original.unwrap_or(true);
+        let entries = self
+            .db
+            .split_entry(
+                &self.user_id,
+                &request.id,
+                &request.split_offsets,
+                keep_original,
+            )
+            .await?;
+
+        // TODO: Re-index the split entries for RAG when we implement
+        // thread-safe LLM handling
+
+        if !keep_original {
+            self.webhook
+                .entry_deleted(&self.config.webhook, &request.id);
+        }
+        for entry in &entries {
+            let event = if entry.id == request.id {
+                EntryEvent::Updated
+            } else {
+                EntryEvent::Created
+            };
+            self.notify(event, entry);
+        }
+        Ok(entries)
+    }
+
+    pub async fn delete_entry(&self, id: &str) -> Result<bool> {
+        let deleted = self.db.delete_entry(id).await?;
+
+        // TODO: Remove from RAG index when we implement thread-safe LLM handling
+
+        if deleted {
+            self.webhook.entry_deleted(&self.config.webhook, id);
+        }
+        Ok(deleted)
+    }
+
+    pub async fn restore_entry(&self, id: &str) -> Result<Option<JournalEntry>> {
+        let entry = self.db.restore_entry(&self.user_id, id).await?;
+        // To webhook consumers the entry was deleted, so it comes back as new
+        if let Some(entry) = &entry {
+            self.notify(EntryEvent::Created, entry);
+        }
+        Ok(entry)
+    }
+
+    pub async fn get_trashed_entries(&self) -> Result<Vec<JournalEntry>> {
+        self.db.get_trashed_entries(&self.user_id).await
+    }
+
+    pub async fn purge_entry(&self, id: &str) -> Result<bool> {
+        let was_live = self
+            .db
+            .get_entry(id)
+            .await?
+            .is_some_and(|entry| entry.deleted_at.is_none());
+        let purged = self.db.purge_entry(&self.user_id, id).await?;
+
+        // Trashing already announced the deletion
+        if purged && was_live {
+            self.webhook.entry_deleted(&self.config.webhook, id);
+        }
+        Ok(purged)
+    }
+
+    pub async fn pin_entry(&self, id: &str) -> Result<Option<JournalEntry>> {
+        self.db
+            .pin_entry(&self.user_id, id, self.config.max_pins)
+            .await
+    }
+
+    pub async fn unpin_entry(&self, id: &str) -> Result<bool> {
+        self.db.unpin_entry(&self.user_id, id).await
+    }
+
+    pub async fn get_pinned_entries(&self) -> Result<Vec<JournalEntry>> {
+        self.db.get_pinned_entries(&self.user_id).await
+    }
+
+    pub async fn reorder_pins(&self, ids: &[String]) -> Result<Vec<JournalEntry>> {
+        self.db.reorder_pins(&self.user_id, ids).await
+    }
+
+    pub fn search_capabilities(&self) -> SearchCapabilities {
+        self.db.search_capabilities()
+    }
+
+    pub async fn search_entries(&self, request: SearchRequest) -> Result<Vec<JournalEntry>> {
+        self.db.search_entries(&self.user_id, request).await
+    }
+
+    pub async fn search_entries_with_total(&self, request: SearchRequest) -> Result<SearchPage> {
+        self.db
+            .search_entries_with_total(&self.user_id, request)
+            .await
+    }
+
+    // Runs each labeled query through entry search and scores the top `k`
+    // (default 10) against the expected ids. Read-only, so it can be run
+    // repeatedly while tuning search weights.
+    pub async fn evaluate_retrieval(
+        &self,
+        queries: Vec<RetrievalQuery>,
+        k: Option<usize>,
+        title_weight: Option<f64>,
+    ) -> Result<RetrievalEvaluation> {
+        let k = k.unwrap_or(DEFAULT_EVALUATION_K);
+        if k == 0 {
+            return Err(anyhow!("k must be at least 1"));
+        }
+
+        let mut scored = Vec::with_capacity(queries.len());
+        for query in &queries {
+            let results = self
+                .db
+                .search_entries(
+                    &self.user_id,
+                    SearchRequest {
+                        query: query.query.clone(),
+                        limit: Some(i32::try_from(k).unwrap_or(i32::MAX)),
+                        title_weight,
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            let retrieved = results.into_iter().map(|entry| entry.id).collect();
+            scored.push(insights::score_retrieval(query, retrieved));
+        }
+        Ok(insights::summarize_retrieval(k, &queries, scored))
+    }
+
+    pub async fn search_by_text(&self, text: &str, top_k: i32) -> Result<Vec<JournalEntry>> {
+        self.db.search_by_text(&self.user_id, text, top_k).await
+    }
+
+    pub async fn contains_topic(&self, query: &str, min_score: Option<f64>) -> Result<TopicCheck> {
+        self.db
+            .contains_topic(&self.user_id, query, min_score.unwrap_or(0.0))
+            .await
+    }
+
+    pub async fn get_entries_by_tag(&self, tag: &str) -> Result<Vec<JournalEntry>> {
+        let tz = self.config.date_display.tz()?;
+        self.db.get_entries_by_tag(&self.user_id, tz, tag).await
+    }
+
+    pub async fn list_tags(&self, min_count: Option<i64>) -> Result<Vec<TagCount>> {
+        self.db
+            .list_tags(&self.user_id, min_count.unwrap_or(1))
+            .await
+    }
+
+    pub async fn get_tag_counts(&self) -> Result<Vec<TagCount>> {
+        self.db.get_tag_counts(&self.user_id).await
+    }
+
+    pub async fn get_mood_stats(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<MoodStats> {
+        let entries = self
+            .db
+            .get_entries_created_between(&self.user_id, from, to)
+            .await?;
+        let tz = self.config.date_display.tz()?;
+        Ok(insights::mood_stats(&entries, tz))
+    }
+
+    pub async fn get_tag_cooccurrence(&self, min_count: Option<i64>) -> Result<Vec<TagPair>> {
+        self.db
+            .get_tag_cooccurrence(&self.user_id, min_count.unwrap_or(1))
+            .await
+    }
+
+    pub async fn get_length_trend(&self, bucket: TrendBucket) -> Result<Vec<LengthTrendPoint>> {
+        let tz = self.config.date_display.tz()?;
+        let entries = self.db.get_entries(&self.user_id, tz).await?;
+        Ok(insights::length_trend(&entries, bucket, tz))
+    }
+
+    // Everything written on `date` (in the user's time zone) for a daily
+    // review card. The recap costs a model call, so it's only produced when
+    // asked for, never for an empty day, and reused until the day's entries
+    // change.
+    pub async fn get_day_summary(
+        &self,
+        date: NaiveDate,
+        include_recap: bool,
+    ) -> Result<DaySummary> {
+        let tz = self.config.date_display.tz()?;
+        let entries = insights::entries_on(self.db.get_entries(&self.user_id, tz).await?, date, tz);
+
+        let mut summary = DaySummary {
+            date: date.format("%Y-%m-%d").to_string(),
+            total_words: entries.iter().map(|e| word_count(&e.body)).sum(),
+            dominant_mood: insights::dominant_mood(&entries),
+            entries: Vec::new(),
+            recap: None,
+            empty_message: None,
+        };
+        if entries.is_empty() {
+            summary.empty_message = Some(format!(
+                "No entries on {}. A few lines are all it takes.",
+                date.format("%B %-d, %Y")
+            ));
+            return Ok(summary);
+        }
+
+        if include_recap {
+            summary.recap = self.day_recap(date, &entries).await?;
+        }
+        summary.entries = entries.into_iter().map(EntrySummary::from).collect();
+        Ok(summary)
+    }
+
+    async fn day_recap(&self, date: NaiveDate, entries: &[JournalEntry]) -> Result<Option<String>> {
+        // Entries kept out of AI features aren't sent to the model
+        let shared: Vec<&JournalEntry> = entries
+            .iter()
+            .filter(|e| e.visibility == Visibility::Normal)
+            .collect();
+        if shared.is_empty() {
+            return Ok(None);
+        }
+
+        let version = shared
+            .iter()
+            .map(|e| format!("{}@{}", e.id, e.updated_at.to_rfc3339()))
+            .collect::<Vec<_>>()
+            .join(",");
+        if let Some((cached_version, recap)) = self.recaps.lock().unwrap().get(&date) {
+            if *cached_version == version {
+                return Ok(Some(recap.clone()));
+            }
+        }
+
+        let mut message = format!(
+            "Write a one-paragraph recap of my journal entries from {}. Only use what these entries say.\n",
+            date.format("%Y-%m-%d")
+        );
+        for entry in &shared {
+            let body: String = entry.body.chars().take(RECAP_ENTRY_CHARS).collect();
+            message.push_str(&format!("\n## {}\n{}\n", entry.title, body));
+        }
+
+        let response = self
+            .ask_python(&PythonChatRequest {
+                user_id: self.user_id.clone(),
+                message,
+                conversation_id: Some(format!("day-recap-{}", date.format("%Y-%m-%d"))),
+                persist: Some(false),
+                ..Default::default()
+            })
+            .await?;
+
+        self.recaps
+            .lock()
+            .unwrap()
+            .insert(date, (version, response.answer.clone()));
+        Ok(Some(response.answer))
+    }
+
+    // A request without a conversation id starts a new conversation; the
+    // generated id comes back on the response so follow-ups can pass it.
+    pub async fn chat(&self, mut request: PythonChatRequest) -> Result<PythonChatResponse> {
+        let conversation_id = request
+            .conversation_id
+            .get_or_insert_with(|| Uuid::new_v4().to_string())
+            .clone();
+
+        // Messages belong to this service's user, whatever id the frontend
+        // still has cached (it changes when the user is reset)
+        request.user_id = self.user_id.clone();
+        let persist = request.persist.unwrap_or(true);
+
+        // Store user message
+        if persist {
+            self.db
+                .create_chat_message(
+                    &self.user_id,
+                    &conversation_id,
+                    &request.message,
+                    true,
+                    None,
+                )
+                .await?;
+        }
+
+        let mut response = match self.ask_python(&request).await {
+            Err(e) if e.is::<ServiceUnreachable>() => {
+                log::warn!("{}; answering from local search", e);
+                self.offline_response(&request).await?
+            }
+            result => result?,
+        };
+        // History is keyed by our id even if the backend echoes its own
+        response.conversation_id = conversation_id.clone();
+
+        // Store AI response
+        if persist {
+            let message_id = self
+                .db
+                .create_chat_message(
+                    &self.user_id,
+                    &conversation_id,
+                    &response.answer,
+                    false,
+                    Some(&response.sources),
+                )
+                .await?;
+            self.record_chat_metadata(&message_id, &response).await;
+        }
+
+        Ok(response)
+    }
+
+    // Re-asks the last user question in a conversation, optionally with
+    // different generation settings, replacing any assistant replies that
+    // followed it with the new answer.
+    pub async fn regenerate_last_response(
+        &self,
+        conversation_id: &str,
+        params: GenerationParams,
+    ) -> Result<PythonChatResponse> {
+        let messages = self
+            .db
+            .get_chat_messages_by_conversation(&self.user_id, conversation_id, None)
+            .await?;
+
+        let last_user_index = messages
+            .iter()
+            .rposition(|m| m.is_user)
+            .ok_or_else(|| anyhow!("No user message to regenerate a response for"))?;
+        let question = &messages[last_user_index];
+
+        let request = PythonChatRequest {
+            user_id: self.user_id.clone(),
+            message: question.content.clone(),
+            conversation_id: Some(conversation_id.to_string()),
+            answer_style: params.answer_style,
+            persona_id: params.persona_id,
+            max_context_chars: params.max_context_chars,
+            ..Default::default()
+        };
+        let response = self.ask_python(&request).await?;
+
+        // Only drop the old reply once a replacement is available, and swap
+        // them in one transaction
+        let answer_id = self
+            .db
+            .replace_conversation_tail(
+                &self.user_id,
+                &question.id,
+                None,
+                &response.answer,
+                &response.sources,
+            )
+            .await?;
+        self.record_chat_metadata(&answer_id, &response).await;
+
+        Ok(response)
+    }
+
+    // Edits an earlier user message and re-runs the conversation from there.
+    // The answer is fetched before anything is changed, so a failed request
+    // leaves the history as it was.
+    pub async fn edit_and_resend(
+        &self,
+        message_id: &str,
+        new_content: &str,
+    ) -> Result<PythonChatResponse> {
+        let message = self
+            .db
+            .get_chat_message(&self.user_id, message_id)
+            .await?
+            .ok_or_else(|| anyhow!("Chat message not found"))?;
+        if !message.is_user {
+            return Err(anyhow!("Only user messages can be edited"));
+        }
+
+        let request = PythonChatRequest {
+            user_id: self.user_id.clone(),
+            message: new_content.to_string(),
+            conversation_id: Some(message.conversation_id.clone()),
+            ..Default::default()
+        };
+        let response = self.ask_python(&request).await?;
+
+        let answer_id = self
+            .db
+            .replace_conversation_tail(
+                &self.user_id,
+                message_id,
+                Some(new_content),
+                &response.answer,
+                &response.sources,
+            )
+            .await?;
+        self.record_chat_metadata(&answer_id, &response).await;
+
+        Ok(response)
+    }
+
+    // Analytics are best effort; a failure here never fails the chat
+    async fn record_chat_metadata(&self, message_id: &str, response: &PythonChatResponse) {
+        if let Err(e) = self
+            .db
+            .record_chat_metadata(message_id, response.sources.len(), response.generation_ms)
+            .await
+        {
+            log::warn!("Failed to record chat metadata: {}", e);
+        }
+    }
+
+    pub async fn get_chat_analytics(&self) -> Result<ChatAnalytics> {
+        self.db.get_chat_analytics(&self.user_id).await
+    }
+
+    pub async fn export_conversation(
+        &self,
+        conversation_id: &str,
+        format: ExportFormat,
+    ) -> Result<String> {
+        let messages = self
+            .db
+            .get_chat_messages_by_conversation(&self.user_id, conversation_id, None)
+            .await?;
+        if messages.is_empty() {
+            return Err(anyhow!("Conversation not found"));
+        }
+
+        export::render_conversation(conversation_id, &messages, format)
+    }
+
+    // Call Python RAG service
+    async fn ask_python(&self, request: &PythonChatRequest) -> Result<PythonChatResponse> {
+        let mapping = &self.config.python_payload;
+        let client = reqwest::Client::new();
+
+        if request.max_context_chars == Some(0) {
+            return Err(anyhow!("max_context_chars must be at least 1"));
+        }
+        let persona = match &request.persona_id {
+            Some(id) => Some(
+                self.db
+                    .get_persona(&self.user_id, id)
+                    .await?
+                    .ok_or_else(|| anyhow!("Persona not found"))?,
+            ),
+            None => None,
+        };
+        let conversation_id = request
+            .conversation_id
+            .as_deref()
+            .unwrap_or(DEFAULT_CONVERSATION_ID);
+        let scope = self
+            .db
+            .get_conversation_scope(&self.user_id, conversation_id)
+            .await?;
+        let tz = self.config.date_display.tz()?;
+        let (body, context) = mapping.build_request(
+            request,
+            persona.as_ref().map(|p| p.system_prompt.as_str()),
+            scope.as_ref().map(|scope| (scope, tz)),
+        );
+        if context.truncated {
+            log::warn!(
+                "Chat message cut to {} characters before forwarding",
+                context.max_context_chars
+            );
+        }
+
+        let url = python::chat_url(
+            self.config
+                .python_service_url
+                .as_deref()
+                .unwrap_or(python::DEFAULT_PYTHON_SERVICE_URL),
+        );
+        let (raw_response, elapsed) = self
+            .generation
+            .run(conversation_id, async {
+                let started = Instant::now();
+                let raw_response = client
+                    .post(&url)
+                    .json(&body)
+                    .send()
+                    .await
+                    .map_err(|e| {
+                        if e.is_connect() {
+                            anyhow!(ServiceUnreachable(e.to_string()))
+                        } else {
+                            anyhow!("Failed to connect to Python service: {}", e)
+                        }
+                    })?
+                    .json::<serde_json::Value>()
+                    .await
+                    .map_err(|e| anyhow!("Failed to parse Python response: {}", e))?;
+                Ok((raw_response, started.elapsed()))
+            })
+            .await?;
+
+        let mut response =
+            mapping.parse_response(raw_response, request.conversation_id.as_deref())?;
+        response.answer = self.config.answer_cleanup.apply(&response.answer);
+        response.context = Some(context);
+        response.generation_ms = Some(elapsed.as_millis() as u64);
+
+        let mut entry_ids = self.drop_excluded_sources(&mut response.sources).await?;
+
+        // The backend retrieves only in-scope entries; sources are checked
+        // again here in case it ignores the scope (a custom backend may)
+        if let Some(scope) = &scope {
+            let in_scope: Vec<String> = self
+                .db
+                .get_entries_by_ids(&self.user_id, &entry_ids)
+                .await?
+                .into_iter()
+                .filter(|entry| scope.matches(entry, insights::entry_day(entry, tz)))
+                .map(|entry| entry.id)
+                .collect();
+            response.sources.retain(|s| {
+                s.get("id")
+                    .and_then(|id| id.as_str())
+                    .is_some_and(|id| in_scope.iter().any(|e| e == id))
+            });
+            entry_ids.retain(|id| in_scope.contains(id));
+        }
+        response.scope = scope;
+
+        // Incognito questions don't count towards retrieval stats either
+        if request.persist != Some(false) {
+            if let Err(e) = self.db.record_retrievals(&self.user_id, &entry_ids).await {
+                log::warn!("Failed to record source retrievals: {}", e);
+            }
+        }
+
+        self.localize_source_dates(&mut response.sources);
+        Ok(response)
+    }
+
+    // The service's index can predate a visibility change, so any source
+    // the user has since kept out of AI features is dropped. Returns the
+    // entry ids of the sources left.
+    async fn drop_excluded_sources(
+        &self,
+        sources: &mut Vec<serde_json::Value>,
+    ) -> Result<Vec<String>> {
+        let mut entry_ids: Vec<String> = sources
+            .iter()
+            .filter_map(|s| s.get("id").and_then(|id| id.as_str()))
+            .map(str::to_string)
+            .collect();
+
+        let excluded = self
+            .db
+            .get_ai_excluded_ids(&self.user_id, &entry_ids)
+            .await?;
+        if !excluded.is_empty() {
+            sources.retain(|s| {
+                s.get("id")
+                    .and_then(|id| id.as_str())
+                    .map_or(true, |id| !excluded.iter().any(|e| e == id))
+            });
+            entry_ids.retain(|id| !excluded.contains(id));
+        }
+        Ok(entry_ids)
+    }
+
+    // Without the chat service there is no answer to give, but the entries
+    // matching the question are still useful. Entries kept out of AI
+    // features or outside the conversation's scope are left out as usual.
+    async fn offline_response(&self, request: &PythonChatRequest) -> Result<PythonChatResponse> {
+        let conversation_id = request
+            .conversation_id
+            .clone()
+            .unwrap_or_else(|| DEFAULT_CONVERSATION_ID.to_string());
+        let mut entries = self
+            .search_entries(SearchRequest {
+                query: request.message.clone(),
+                limit: Some(OFFLINE_SOURCE_LIMIT),
+                ..Default::default()
+            })
+            .await?;
+
+        let ids: Vec<String> = entries.iter().map(|e| e.id.clone()).collect();
+        let excluded = self.db.get_ai_excluded_ids(&self.user_id, &ids).await?;
+        entries.retain(|entry| !excluded.contains(&entry.id));
+        let scope = self
+            .db
+            .get_conversation_scope(&self.user_id, &conversation_id)
+            .await?;
+        if let Some(scope) = &scope {
+            let tz = self.config.date_display.tz()?;
+            entries.retain(|entry| scope.matches(entry, insights::entry_day(entry, tz)));
+        }
+
+        let answer = if entries.is_empty() {
+            "The assistant is offline and no entries matched your question."
+        } else {
+            "The assistant is offline, so here are the entries that best match your question."
+        };
+        let mut sources: Vec<serde_json::Value> = entries
+            .iter()
+            .map(|entry| {
+                serde_json::json!({
+                    "id": entry.id,
+                    "title": entry.title,
+                    "date": entry.created_at.to_rfc3339(),
+                    "mood": entry.mood,
+                    "tags": entry.tags.clone().unwrap_or_default(),
+                })
+            })
+            .collect();
+        self.localize_source_dates(&mut sources);
+
+        Ok(PythonChatResponse {
+            answer: answer.to_string(),
+            sources,
+            conversation_id,
+            context: None,
+            generation_ms: None,
+            scope,
+            offline: true,
+        })
+    }
+
+    // Sources carry UTC timestamps; show them as dates in the user's zone
+    fn localize_source_dates(&self, sources: &mut [serde_json::Value]) {
+        for source in sources {
+            let Some(date) = source.get_mut("date") else {
+                continue;
+            };
+            if let Some(local) = date
+                .as_str()
+                .and_then(|d| self.config.date_display.format_rfc3339(d))
+            {
+                *date = serde_json::Value::String(local);
+            }
+        }
+    }
+
+    pub async fn get_most_referenced_entries(&self, limit: i32) -> Result<Vec<ReferencedEntry>> {
+        self.db
+            .get_most_referenced_entries(&self.user_id, limit)
+            .await
+    }
+
+    // Runs the save-time transforms over `body` in place, returning the text
+    // as it was when they changed it
+    fn transform_body(&self, body: &mut String) -> Option<String> {
+        let mut pipeline = self.config.transforms.pipeline();
+        pipeline.extend(&self.custom_transforms);
+        let transformed = pipeline.apply(body);
+        (transformed != *body).then(|| std::mem::replace(body, transformed))
+    }
+
+    // Keeps the text as typed retrievable from the entry's history
+    async fn save_original(&self, entry: &JournalEntry, original: &str) -> Result<()> {
+        self.db
+            .save_entry_version(
+                &entry.id,
+                &entry.title,
+                original,
+                entry.mood.as_deref(),
+                entry.tags.as_deref(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    fn notify(&self, event: EntryEvent, entry: &JournalEntry) {
+        self.webhook
+            .entry_changed(&self.config.webhook, event, entry);
+    }
+
+    // Stops the answer being generated (or queued) for a conversation; the
+    // question stays in history, like one whose answer failed
+    pub fn cancel_generation(&self, conversation_id: &str) -> bool {
+        self.generation.cancel(conversation_id)
+    }
+
+    pub fn generation_queue_status(&self) -> GenerationQueueStatus {
+        self.generation.status()
+    }
+
+    // Tags are stored trimmed. Returns the scope now in effect, `None` once
+    // cleared.
+    pub async fn set_conversation_scope(
+        &self,
+        conversation_id: &str,
+        scope: Option<ConversationScope>,
+    ) -> Result<Option<ConversationScope>> {
+        let mut scope = scope.unwrap_or_default();
+        scope.validate()?;
+        for tag in &mut scope.tags {
+            *tag = tag.trim().to_string();
+        }
+        self.db
+            .set_conversation_scope(&self.user_id, conversation_id, &scope)
+            .await?;
+        Ok((!scope.is_empty()).then_some(scope))
+    }
+
+    pub async fn get_conversation_scope(
+        &self,
+        conversation_id: &str,
+    ) -> Result<Option<ConversationScope>> {
+        self.db
+            .get_conversation_scope(&self.user_id, conversation_id)
+            .await
+    }
+
+    pub async fn get_personas(&self) -> Result<Vec<Persona>> {
+        self.db.get_personas(&self.user_id).await
+    }
+
+    pub async fn create_persona(&self, name: &str, system_prompt: &str) -> Result<Persona> {
+        self.db
+            .create_persona(&self.user_id, name, system_prompt)
+            .await
+    }
+
+    pub async fn update_persona(
+        &self,
+        id: &str,
+        name: Option<&str>,
+        system_prompt: Option<&str>,
+    ) -> Result<Option<Persona>> {
+        self.db
+            .update_persona(&self.user_id, id, name, system_prompt)
+            .await
+    }
+
+    pub async fn delete_persona(&self, id: &str) -> Result<bool> {
+        self.db.delete_persona(&self.user_id, id).await
+    }
+
+    pub async fn get_chat_history(&self) -> Result<Vec<ChatMessage>> {
+        self.db.get_chat_messages(&self.user_id, Some(50)).await
+    }
+
+    pub async fn list_conversations(&self) -> Result<Vec<ConversationSummary>> {
+        self.db.list_conversations(&self.user_id).await
+    }
+
+    pub async fn bookmark_message(&self, id: &str, bookmarked: bool) -> Result<bool> {
+        self.db
+            .set_chat_message_bookmarked(&self.user_id, id, bookmarked)
+            .await
+    }
+
+    pub async fn get_bookmarked_messages(&self) -> Result<Vec<ChatMessage>> {
+        self.db.get_bookmarked_messages(&self.user_id).await
+    }
+
+    pub async fn delete_chat_message(&self, id: &str) -> Result<bool> {
+        self.db.delete_chat_message(&self.user_id, id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    async fn test_service() -> Service {
+        Service::connect("sqlite::memory:", ServiceConfig::default())
+            .await
+            .unwrap()
+    }
+
+    fn new_entry(title: &str, body: &str) -> CreateEntryRequest {
+        CreateEntryRequest {
+            title: title.to_string(),
+            body: body.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn ai_excluded_entries_are_dropped_from_sources() {
+        let service = test_service().await;
+        let shared = service
+            .create_entry(new_entry("Shared", "a walk"))
+            .await
+            .unwrap();
+        let excluded = service
+            .create_entry(CreateEntryRequest {
+                visibility: Some(Visibility::AiExcluded),
+                ..new_entry("Excluded", "a doctor's visit")
+            })
+            .await
+            .unwrap();
+
+        let mut sources = vec![json!({ "id": shared.id }), json!({ "id": excluded.id })];
+        let kept = service.drop_excluded_sources(&mut sources).await.unwrap();
+        assert_eq!(kept, [shared.id.as_str()]);
+        assert_eq!(sources, [json!({ "id": shared.id })]);
+    }
+
+    #[tokio::test]
+    async fn entries_excluded_by_tag_drop_out_of_sources() {
+        let service = test_service().await;
+        let checkup = service
+            .create_entry(CreateEntryRequest {
+                tags: Some(vec!["health".to_string()]),
+                ..new_entry("Checkup", "blood pressure is fine")
+            })
+            .await
+            .unwrap();
+        let hike = service
+            .create_entry(new_entry("Hike", "up the ridge"))
+            .await
+            .unwrap();
+        let sources = vec![json!({ "id": checkup.id }), json!({ "id": hike.id })];
+
+        let mut before = sources.clone();
+        service.drop_excluded_sources(&mut before).await.unwrap();
+        assert_eq!(before, sources);
+
+        let changed = service
+            .set_visibility_by_tag("health", Visibility::AiExcluded)
+            .await
+            .unwrap();
+        assert_eq!(changed, 1);
+        let mut after = sources.clone();
+        service.drop_excluded_sources(&mut after).await.unwrap();
+        assert_eq!(after, [json!({ "id": hike.id })]);
+
+        // Nor can it be chunked for AI features any more
+        assert!(service
+            .chunk_entry(&checkup.id, ChunkOptions::default())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn mood_stats_bucket_by_the_local_day() {
+        let service = test_service().await.with_config(ServiceConfig {
+            date_display: DateDisplay {
+                timezone: Some("Asia/Tokyo".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let entry = service
+            .create_entry(CreateEntryRequest {
+                mood: Some("calm".to_string()),
+                ..new_entry("Night", "quiet")
+            })
+            .await
+            .unwrap();
+        // 23:30 UTC is already the next morning in Tokyo
+        service
+            .db
+            .set_entry_created_at(
+                &service.user_id,
+                &entry.id,
+                "2026-01-01T23:30:00Z".parse().unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let stats = service.get_mood_stats(None, None).await.unwrap();
+        let day = NaiveDate::from_ymd_opt(2026, 1, 2).unwrap();
+        assert_eq!(stats.daily.keys().collect::<Vec<_>>(), [&day]);
+        assert_eq!(stats.daily[&day]["calm"], 1);
+    }
+
+    #[tokio::test]
+    async fn reset_counts_entry_rows_and_returns_the_new_user() {
+        let mut service = test_service().await;
+        let old_user = service.user_id().to_string();
+        let entry = service
+            .create_entry(new_entry("Draft", "first"))
+            .await
+            .unwrap();
+        service
+            .update_entry(UpdateEntryRequest {
+                id: entry.id.clone(),
+                body: Some("second".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let counts = service
+            .reset_user_data(RESET_CONFIRMATION, true)
+            .await
+            .unwrap();
+        assert_eq!(counts.entries, 1);
+        assert_eq!(counts.entry_versions, 1);
+        assert!(counts.user_deleted);
+        assert_ne!(counts.user_id, old_user);
+        assert_eq!(counts.user_id, service.user_id());
+    }
+
+    #[tokio::test]
+    async fn exports_leave_out_hidden_entries_unless_asked() {
+        let service = test_service().await;
+        let shared = service
+            .create_entry(new_entry("Garden", "tomatoes"))
+            .await
+            .unwrap();
+        let hidden = service
+            .create_entry(CreateEntryRequest {
+                visibility: Some(Visibility::Private),
+                ..new_entry("Diary", "secret")
+            })
+            .await
+            .unwrap();
+
+        let exported = service.get_entries_with_fields(false).await.unwrap();
+        assert_eq!(
+            exported.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(),
+            [shared.id.as_str()]
+        );
+        let everything = service.get_entries_with_fields(true).await.unwrap();
+        assert!(everything.iter().any(|e| e.id == hidden.id));
+    }
+}
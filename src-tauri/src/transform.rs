@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+// Query parameters added by ad and newsletter links, dropped from URLs by
+// StripTrackingParams. Anything starting with "utm_" is dropped as well.
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "dclid", "msclkid", "mc_cid", "mc_eid", "igshid", "yclid", "_hsenc", "_hsmi",
+];
+
+// A rewrite of an entry's body applied on save. Implement this to add your
+// own step and register it with Service::with_transform.
+pub trait BodyTransform: Send + Sync {
+    fn name(&self) -> &str;
+    fn apply(&self, body: &str) -> String;
+}
+
+// Which built-in transforms run on save. All are off by default.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransformSettings {
+    pub normalize_whitespace: bool,
+    pub strip_tracking_params: bool,
+    // Whole words replaced on save, e.g. "tmrw" -> "tomorrow". Matching is
+    // case-sensitive; an empty map turns expansion off.
+    pub abbreviations: BTreeMap<String, String>,
+}
+
+impl TransformSettings {
+    pub fn validate(&self) -> Result<()> {
+        for abbreviation in self.abbreviations.keys() {
+            if abbreviation.is_empty() || !abbreviation.chars().all(is_word_char) {
+                return Err(anyhow!(
+                    "Abbreviations must be a single word, got {:?}",
+                    abbreviation
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // The enabled built-ins, in the order they run: expansion first so
+    // whitespace normalization tidies up after it
+    pub fn pipeline(&self) -> TransformPipeline {
+        let mut pipeline = TransformPipeline::default();
+        if !self.abbreviations.is_empty() {
+            pipeline.push(Arc::new(ExpandAbbreviations {
+                abbreviations: self.abbreviations.clone(),
+            }));
+        }
+        if self.strip_tracking_params {
+            pipeline.push(Arc::new(StripTrackingParams));
+        }
+        if self.normalize_whitespace {
+            pipeline.push(Arc::new(NormalizeWhitespace));
+        }
+        pipeline
+    }
+}
+
+// Transforms run in order, each on the previous one's output
+#[derive(Clone, Default)]
+pub struct TransformPipeline {
+    transforms: Vec<Arc<dyn BodyTransform>>,
+}
+
+impl TransformPipeline {
+    pub fn push(&mut self, transform: Arc<dyn BodyTransform>) {
+        self.transforms.push(transform);
+    }
+
+    pub fn extend(&mut self, other: &TransformPipeline) {
+        self.transforms.extend(other.transforms.iter().cloned());
+    }
+
+    pub fn apply(&self, body: &str) -> String {
+        let mut body = body.to_string();
+        for transform in &self.transforms {
+            let next = transform.apply(&body);
+            if next != body {
+                log::debug!("Body transform '{}' changed the entry", transform.name());
+                body = next;
+            }
+        }
+        body
+    }
+}
+
+// Trailing spaces are removed from each line, runs of blank lines become a
+// single blank line, and the body is trimmed. Indentation is kept.
+pub struct NormalizeWhitespace;
+
+impl BodyTransform for NormalizeWhitespace {
+    fn name(&self) -> &str {
+        "normalize_whitespace"
+    }
+
+    fn apply(&self, body: &str) -> String {
+        let mut lines: Vec<&str> = Vec::new();
+        for line in body.lines().map(str::trim_end) {
+            if line.is_empty() && lines.last().map_or(true, |last| last.is_empty()) {
+                continue;
+            }
+            lines.push(line);
+        }
+        while lines.last().is_some_and(|last| last.is_empty()) {
+            lines.pop();
+        }
+        lines.join("\n")
+    }
+}
+
+// Removes tracking query parameters from http(s) links, dropping the `?`
+// when nothing else is left
+pub struct StripTrackingParams;
+
+impl BodyTransform for StripTrackingParams {
+    fn name(&self) -> &str {
+        "strip_tracking_params"
+    }
+
+    fn apply(&self, body: &str) -> String {
+        let mut out = String::with_capacity(body.len());
+        let mut rest = body;
+        while let Some(start) = find_url(rest) {
+            out.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find(|c: char| c.is_whitespace() || matches!(c, ')' | ']' | '>' | '"' | '\''))
+                .map_or(rest.len(), |len| start + len);
+            // Punctuation ending a sentence isn't part of the link
+            let url = rest[start..end].trim_end_matches(['.', ',', ';', ':', '!', '?']);
+            out.push_str(&strip_url(url));
+            rest = &rest[start + url.len()..];
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+fn find_url(text: &str) -> Option<usize> {
+    match (text.find("http://"), text.find("https://")) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn strip_url(url: &str) -> String {
+    let (url, fragment) = match url.split_once('#') {
+        Some((url, fragment)) => (url, Some(fragment)),
+        None => (url, None),
+    };
+    let Some((base, query)) = url.split_once('?') else {
+        return match fragment {
+            Some(fragment) => format!("{}#{}", url, fragment),
+            None => url.to_string(),
+        };
+    };
+
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|param| {
+            let name = param.split('=').next().unwrap_or_default();
+            !(name.starts_with("utm_") || TRACKING_PARAMS.contains(&name))
+        })
+        .collect();
+    let mut stripped = base.to_string();
+    if !kept.is_empty() {
+        stripped.push('?');
+        stripped.push_str(&kept.join("&"));
+    }
+    if let Some(fragment) = fragment {
+        stripped.push('#');
+        stripped.push_str(fragment);
+    }
+    stripped
+}
+
+// Replaces whole words found in the map; "tmrw" in "tmrw's" is expanded but
+// not in "tmrwx"
+pub struct ExpandAbbreviations {
+    pub abbreviations: BTreeMap<String, String>,
+}
+
+impl BodyTransform for ExpandAbbreviations {
+    fn name(&self) -> &str {
+        "expand_abbreviations"
+    }
+
+    fn apply(&self, body: &str) -> String {
+        let mut out = String::with_capacity(body.len());
+        let mut word_start = None;
+        for (i, c) in body.char_indices() {
+            if is_word_char(c) {
+                word_start.get_or_insert(i);
+                continue;
+            }
+            if let Some(start) = word_start.take() {
+                self.push_word(&mut out, &body[start..i]);
+            }
+            out.push(c);
+        }
+        if let Some(start) = word_start {
+            self.push_word(&mut out, &body[start..]);
+        }
+        out
+    }
+}
+
+impl ExpandAbbreviations {
+    fn push_word(&self, out: &mut String, word: &str) {
+        match self.abbreviations.get(word) {
+            Some(expansion) => out.push_str(expansion),
+            None => out.push_str(word),
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_whitespace() {
+        assert_eq!(
+            NormalizeWhitespace.apply("\n\nfirst  \n\n\n  indented\t\n\n"),
+            "first\n\n  indented"
+        );
+    }
+
+    #[test]
+    fn strip_tracking_params() {
+        assert_eq!(
+            StripTrackingParams.apply(
+                "Read https://example.com/post?utm_source=mail&id=7#top, then \
+                 (https://example.com/?fbclid=abc)."
+            ),
+            "Read https://example.com/post?id=7#top, then (https://example.com/)."
+        );
+    }
+
+    #[test]
+    fn expand_abbreviations() {
+        let expand = ExpandAbbreviations {
+            abbreviations: BTreeMap::from([("tmrw".to_string(), "tomorrow".to_string())]),
+        };
+        assert_eq!(
+            expand.apply("tmrw's plan, not tmrwx or Tmrw"),
+            "tomorrow's plan, not tmrwx or Tmrw"
+        );
+    }
+}
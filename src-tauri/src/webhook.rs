@@ -0,0 +1,193 @@
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tokio::sync::mpsc;
+
+use crate::db::{JournalEntry, Visibility};
+
+const SIGNATURE_HEADER: &str = "X-Journal-Signature";
+// Deliveries kept while the endpoint is unreachable; the oldest are dropped
+// beyond this
+const MAX_PENDING: usize = 1000;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Outbound webhook fired when entries change. Off unless explicitly enabled.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub enabled: bool,
+    pub url: String,
+    // When set, each payload is signed with HMAC-SHA256 and the hex digest
+    // sent as `X-Journal-Signature: sha256=<digest>`
+    pub secret: Option<String>,
+}
+
+impl WebhookConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let url = reqwest::Url::parse(&self.url)
+            .map_err(|e| anyhow!("Invalid webhook URL '{}': {}", self.url, e))?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(anyhow!("Webhook URL must use http or https"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryEvent {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl EntryEvent {
+    fn name(self) -> &'static str {
+        match self {
+            EntryEvent::Created => "entry.created",
+            EntryEvent::Updated => "entry.updated",
+            EntryEvent::Deleted => "entry.deleted",
+        }
+    }
+}
+
+struct Delivery {
+    url: String,
+    secret: Option<String>,
+    body: String,
+}
+
+// Delivers webhook payloads in order from a background task, retrying with
+// exponential backoff so nothing is lost while the endpoint is offline.
+#[derive(Default)]
+pub struct WebhookSender {
+    // Started on first use, since that's when a runtime is guaranteed
+    queue: OnceLock<mpsc::UnboundedSender<Delivery>>,
+}
+
+impl WebhookSender {
+    pub fn entry_changed(&self, config: &WebhookConfig, event: EntryEvent, entry: &JournalEntry) {
+        self.send(config, event, entry_payload(entry));
+    }
+
+    pub fn entry_deleted(&self, config: &WebhookConfig, id: &str) {
+        self.send(config, EntryEvent::Deleted, json!({ "id": id }));
+    }
+
+    fn send(&self, config: &WebhookConfig, event: EntryEvent, entry: Value) {
+        if !config.enabled {
+            return;
+        }
+
+        let body = json!({
+            "event": event.name(),
+            "sent_at": Utc::now().to_rfc3339(),
+            "entry": entry,
+        })
+        .to_string();
+
+        let queue = self.queue.get_or_init(|| {
+            let (tx, rx) = mpsc::unbounded_channel();
+            tokio::spawn(deliver_all(rx));
+            tx
+        });
+        let _ = queue.send(Delivery {
+            url: config.url.clone(),
+            secret: config.secret.clone(),
+            body,
+        });
+    }
+}
+
+// Hidden entries are announced without their content: only the id and
+// metadata leave the app
+fn entry_payload(entry: &JournalEntry) -> Value {
+    if entry.visibility == Visibility::Normal {
+        return json!(entry);
+    }
+    json!({
+        "id": entry.id,
+        "userId": entry.user_id,
+        "createdAt": entry.created_at,
+        "updatedAt": entry.updated_at,
+        "entryDate": entry.entry_date,
+        "visibility": entry.visibility,
+    })
+}
+
+async fn deliver_all(mut rx: mpsc::UnboundedReceiver<Delivery>) {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    let mut pending: VecDeque<Delivery> = VecDeque::new();
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        if pending.is_empty() {
+            match rx.recv().await {
+                Some(delivery) => pending.push_back(delivery),
+                None => return,
+            }
+        }
+        while let Ok(delivery) = rx.try_recv() {
+            pending.push_back(delivery);
+        }
+        while pending.len() > MAX_PENDING {
+            pending.pop_front();
+            log::warn!("Webhook queue full, dropping the oldest delivery");
+        }
+
+        let delivery = &pending[0];
+        match deliver(&client, delivery).await {
+            Ok(()) => {
+                pending.pop_front();
+                backoff = INITIAL_BACKOFF;
+            }
+            Err(e) => {
+                log::warn!(
+                    "Webhook delivery to {} failed, retrying in {:?}: {}",
+                    delivery.url,
+                    backoff,
+                    e
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+async fn deliver(client: &reqwest::Client, delivery: &Delivery) -> Result<()> {
+    let mut request = client
+        .post(&delivery.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(delivery.body.clone());
+    if let Some(secret) = &delivery.secret {
+        request = request.header(
+            SIGNATURE_HEADER,
+            format!("sha256={}", sign(secret, &delivery.body)),
+        );
+    }
+
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
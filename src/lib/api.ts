@@ -34,6 +34,7 @@ export interface ChatResponse {
   answer: string;
   sources: any[];
   conversation_id: string;
+  offline?: boolean;
 }
 
 // Journal API
@@ -65,7 +66,8 @@ export const journalApi = {
 
 // AI Chat API
 export const chatApi = {
-  async sendMessage(message: string): Promise<ChatResponse> {
+  // Omit conversationId to start a new conversation; its id comes back on the response
+  async sendMessage(message: string, conversationId?: string): Promise<ChatResponse> {
     const userId = useAppStore.getState().userId;
     if (!userId) {
       throw new Error('User not initialized');
@@ -75,7 +77,7 @@ export const chatApi = {
       request: {
         user_id: userId,
         message,
-        conversation_id: null,
+        conversation_id: conversationId ?? null,
       },
     });
     
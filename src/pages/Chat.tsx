@@ -8,6 +8,7 @@ import { motion, AnimatePresence } from 'framer-motion';
 export function Chat() {
   const { chatMessages, addChatMessage, isChatLoading, setIsChatLoading } = useAppStore();
   const [message, setMessage] = useState('');
+  const [conversationId, setConversationId] = useState<string>();
   const messagesEndRef = useRef<HTMLDivElement>(null);
 
   const scrollToBottom = () => {
@@ -33,7 +34,8 @@ export function Chat() {
     setIsChatLoading(true);
 
     try {
-      const response = await chatApi.sendMessage(userMessage.content);
+      const response = await chatApi.sendMessage(userMessage.content, conversationId);
+      setConversationId(response.conversation_id);
 
       const aiMessage = {
         id: `ai-${Date.now()}`,